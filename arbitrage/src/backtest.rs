@@ -0,0 +1,309 @@
+use crate::gas::GasModel;
+use crate::logs::{group_by_block, LogError, RawLog};
+use crate::opportunity::{Opportunity, OpportunityConfig};
+use crate::token::Token;
+use crate::v2;
+use crate::v3::{
+    calc_two_pool_arb_gas, find_optimal_arb, two_pool_opportunity, uniswap_v3_pool, v3_quote,
+    ARB_FEE,
+};
+use crate::v3_logs::V3LogReplayer;
+
+#[derive(Copy, Clone, Debug)]
+pub struct BacktestConfig {
+    pub token_in: Token,
+    pub max_amt_in: f64,
+    pub gas: GasModel,
+    pub opportunity: OpportunityConfig,
+}
+
+// [Trade] is one accepted opportunity and what happened when it was sent. Profits and gas are in units of the opportunity's input token. A reverted trade still pays gas.
+#[derive(Clone, Debug)]
+pub struct Trade {
+    pub block_number: u64,
+    pub route: (usize, usize),
+    pub opportunity: Opportunity,
+    pub realized_profit: f64,
+    pub gas_cost: f64,
+    pub reverted: bool,
+}
+
+// Missed profit is what the search expected to net on each trade minus what the trade actually netted, counting only shortfalls.
+#[derive(Clone, Debug, Default)]
+pub struct BacktestReport {
+    pub blocks: usize,
+    pub trades: Vec<Trade>,
+    pub captured_profit: f64,
+    pub missed_profit: f64,
+    pub gas_spent: f64,
+}
+
+impl BacktestReport {
+    pub fn opportunities(&self) -> usize {
+        self.trades.len()
+    }
+
+    pub fn reverted(&self) -> usize {
+        self.trades.iter().filter(|t| t.reverted).count()
+    }
+
+    fn record(&mut self, trade: Trade) {
+        self.captured_profit += trade.realized_profit;
+        self.missed_profit += (trade.opportunity.net_profit - trade.realized_profit).max(0.);
+        self.gas_spent += trade.gas_cost;
+        self.trades.push(trade);
+    }
+}
+
+// [Backtest] replays recorded logs for two V3 pools. After each block it searches both routes between the pools, and sends every accepted opportunity into the next block: the block's own events land first, then the trade executes against the resulting state with each leg held to its min_out.
+pub struct Backtest {
+    pools: [uniswap_v3_pool; 2],
+    replayers: [V3LogReplayer; 2],
+    reference: v2::Pool,
+    config: BacktestConfig,
+}
+
+impl Backtest {
+    pub fn new(
+        pool1: (&str, uniswap_v3_pool),
+        pool2: (&str, uniswap_v3_pool),
+        reference: v2::Pool,
+        config: BacktestConfig,
+    ) -> Self {
+        Backtest {
+            replayers: [V3LogReplayer::new(pool1.0), V3LogReplayer::new(pool2.0)],
+            pools: [pool1.1, pool2.1],
+            reference,
+            config,
+        }
+    }
+
+    pub fn pool(&self, index: usize) -> &uniswap_v3_pool {
+        &self.pools[index]
+    }
+
+    fn apply_block(
+        replayers: &mut [V3LogReplayer; 2],
+        pools: &mut [uniswap_v3_pool; 2],
+        block: &[RawLog],
+    ) -> Result<(), LogError> {
+        for log in block {
+            for (replayer, pool) in replayers.iter_mut().zip(pools.iter_mut()) {
+                replayer.apply_log(pool, log)?;
+            }
+        }
+        Ok(())
+    }
+
+    // [search] returns the accepted opportunity, if any, for sending token_in through from and then to.
+    fn search(&self, from: usize, to: usize) -> Option<Opportunity> {
+        let config = &self.config;
+        let amount_in = find_optimal_arb(
+            &self.pools[from],
+            &self.pools[to],
+            config.token_in,
+            config.max_amt_in,
+            &config.gas,
+            &self.reference,
+        );
+        let opportunity = two_pool_opportunity(
+            amount_in,
+            &self.pools[from],
+            &self.pools[to],
+            config.token_in,
+            &config.gas,
+            &self.reference,
+            &config.opportunity,
+        )
+        .ok()?;
+        if config.opportunity.accepts(&opportunity) {
+            Some(opportunity)
+        } else {
+            None
+        }
+    }
+
+    fn execute(
+        &self,
+        pools: &[uniswap_v3_pool; 2],
+        route: (usize, usize),
+        opportunity: &Opportunity,
+    ) -> (f64, f64, bool) {
+        let (from, to) = (&pools[route.0], &pools[route.1]);
+        let token_in = opportunity.token_in;
+        let amount_in = opportunity.amount_in;
+        let gas_cost = calc_two_pool_arb_gas(
+            amount_in,
+            from,
+            to,
+            token_in,
+            &self.config.gas,
+            &self.reference,
+        );
+        let mid = v3_quote(from, token_in, amount_in, ARB_FEE);
+        if mid < opportunity.legs[0].min_out {
+            return (-gas_cost, gas_cost, true);
+        }
+        let out = v3_quote(to, token_in.other(), mid, ARB_FEE);
+        if out < opportunity.legs[1].min_out {
+            return (-gas_cost, gas_cost, true);
+        }
+        (out - amount_in - gas_cost, gas_cost, false)
+    }
+
+    // [run] replays logs, which must already be ordered, and leaves the pools at their state after the last block.
+    pub fn run(&mut self, logs: &[RawLog]) -> Result<BacktestReport, LogError> {
+        let blocks = group_by_block(logs);
+        let mut report = BacktestReport::default();
+        for (i, (block_number, block)) in blocks.iter().enumerate() {
+            Backtest::apply_block(&mut self.replayers, &mut self.pools, block)?;
+            report.blocks += 1;
+
+            let found: Vec<((usize, usize), Opportunity)> = [(0, 1), (1, 0)]
+                .into_iter()
+                .filter_map(|route| self.search(route.0, route.1).map(|o| (route, o)))
+                .collect();
+            if found.is_empty() {
+                continue;
+            }
+
+            let mut next = self.pools.clone();
+            if let Some((_, next_block)) = blocks.get(i + 1) {
+                let mut replayers = self.replayers.clone();
+                Backtest::apply_block(&mut replayers, &mut next, next_block)?;
+            }
+            for (route, opportunity) in found {
+                let (realized_profit, gas_cost, reverted) =
+                    self.execute(&next, route, &opportunity);
+                report.record(Trade {
+                    block_number: *block_number,
+                    route,
+                    opportunity,
+                    realized_profit,
+                    gas_cost,
+                    reverted,
+                });
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::v3_logs::{INITIALIZE_TOPIC, MINT_TOPIC, SWAP_TOPIC};
+
+    const POOL1: &str = "0x0000000000000000000000000000000000000001";
+    const POOL2: &str = "0x0000000000000000000000000000000000000002";
+    const OWNER: &str = "0x0000000000000000000000000000000000000000000000000000000000000003";
+    const SQRT_PRICE: i128 = 5602277097478614198912276234240;
+
+    fn word(value: i128) -> String {
+        let pad = if value < 0 { "f" } else { "0" };
+        format!("{}{:032x}", pad.repeat(32), value as u128)
+    }
+
+    fn log(address: &str, block: u64, index: u64, topics: Vec<String>, data: &[i128]) -> RawLog {
+        RawLog {
+            address: address.to_string(),
+            topics,
+            data: format!("0x{}", data.iter().map(|v| word(*v)).collect::<String>()),
+            block_number: block,
+            log_index: index,
+            removed: false,
+        }
+    }
+
+    fn seed(address: &str, index: u64, liquidity: i128) -> Vec<RawLog> {
+        vec![
+            log(
+                address,
+                1,
+                index,
+                vec![INITIALIZE_TOPIC.to_string()],
+                &[SQRT_PRICE, 85176],
+            ),
+            log(
+                address,
+                1,
+                index + 1,
+                vec![
+                    MINT_TOPIC.to_string(),
+                    OWNER.to_string(),
+                    format!("0x{}", word(-86000)),
+                    format!("0x{}", word(86000)),
+                ],
+                &[0, liquidity, 1000000000, 1000000000],
+            ),
+        ]
+    }
+
+    fn price_move(block: u64, sqrt_price_x96: i128) -> RawLog {
+        log(
+            POOL1,
+            block,
+            0,
+            vec![SWAP_TOPIC.to_string(), OWNER.to_string(), OWNER.to_string()],
+            &[1, -1, sqrt_price_x96, 1000000000000000000, 85176],
+        )
+    }
+
+    fn empty_pool() -> uniswap_v3_pool {
+        uniswap_v3_pool::new(Token::Eth, Token::Dai, 0.)
+    }
+
+    fn backtest() -> Backtest {
+        let reference = v2::Pool::new(Token::Eth, Token::Dai, 4., 20000.);
+        Backtest::new(
+            (POOL1, empty_pool()),
+            (POOL2, empty_pool()),
+            reference,
+            BacktestConfig {
+                token_in: Token::Eth,
+                max_amt_in: 100000.,
+                gas: GasModel::default(),
+                opportunity: OpportunityConfig::default(),
+            },
+        )
+    }
+
+    fn history(moves: &[RawLog]) -> Vec<RawLog> {
+        let mut logs = seed(POOL1, 0, 1000000000000000000);
+        logs.extend(seed(POOL2, 2, 1000000000000000000));
+        logs.extend_from_slice(moves);
+        logs
+    }
+
+    #[test]
+    fn captures_opportunity_that_survives() {
+        let logs = history(&[price_move(2, SQRT_PRICE / 10 * 11)]);
+
+        let report = backtest().run(&logs).unwrap();
+
+        assert_eq!(report.blocks, 2);
+        assert!(report.opportunities() > 0);
+        assert_eq!(report.reverted(), 0);
+        assert!(report.trades.iter().all(|t| t.block_number == 2));
+        assert!(report.captured_profit > 0.);
+        assert!(report.gas_spent > 0.);
+    }
+
+    #[test]
+    fn trade_reverts_when_next_block_closes_the_gap() {
+        let logs = history(&[
+            price_move(2, SQRT_PRICE / 10 * 11),
+            price_move(3, SQRT_PRICE),
+        ]);
+
+        let report = backtest().run(&logs).unwrap();
+
+        assert_eq!(report.blocks, 3);
+        assert!(report.opportunities() > 0);
+        assert_eq!(report.reverted(), report.opportunities());
+        assert_eq!(report.captured_profit, -report.gas_spent);
+        let expected: f64 = report.trades.iter().map(|t| t.opportunity.net_profit).sum();
+        assert_eq!(report.missed_profit, expected + report.gas_spent);
+    }
+}
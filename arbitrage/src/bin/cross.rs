@@ -0,0 +1,50 @@
+use arbitrage::cross::{search_cross_arb, CrossFees};
+use arbitrage::gas::GasModel;
+use arbitrage::opportunity::OpportunityConfig;
+use arbitrage::token::Token;
+use arbitrage::v2::Pool;
+use arbitrage::v3::{uniswap_v3_pool, Trader};
+use std::sync::RwLock;
+
+fn main() {
+    let trader = Trader {
+        id: 2,
+        amt_eth: RwLock::new(0.),
+        amt_dai: RwLock::new(0.),
+    };
+    let mut v3_pool =
+        uniswap_v3_pool::new(Token::Eth, Token::Dai, 5602277097478614198912276234240.);
+    v3_pool.mint(&trader, -86000, 86000, 1000000.).unwrap();
+
+    let v2_pool = Pool::new(Token::Eth, Token::Dai, 1000., 4000000.);
+
+    let fees = CrossFees {
+        v2_fee: 0.003,
+        v3_fee: 0.003,
+    };
+
+    let gas = GasModel::default();
+    let config = OpportunityConfig::default();
+
+    for token_in in [Token::Eth, Token::Dai] {
+        match search_cross_arb(
+            &v2_pool, &v3_pool, token_in, &fees, 1000000., &gas, &v2_pool, &config,
+        ) {
+            Some(arb) if config.accepts(&arb.opportunity) => {
+                println!("{:?} via {:?}: {:?}", token_in, arb.route, arb.opportunity)
+            }
+            _ => println!("No actionable {:?} opportunity", token_in),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn report_cross_arb() {
+        main()
+    }
+}
@@ -0,0 +1,15 @@
+use arbitrage::cli::{self, CliError};
+use std::env;
+use std::io;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = cli::run(&args, &mut io::stdout()) {
+        eprintln!("error: {}", e);
+        if let CliError::Usage(_) = e {
+            eprintln!("\n{}", cli::USAGE);
+        }
+        process::exit(2);
+    }
+}
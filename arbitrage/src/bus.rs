@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+pub type PoolId = usize;
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct PoolUpdated {
+    pub pool_id: PoolId,
+    pub version: u64,
+}
+
+// [UpdateBus] fans PoolUpdated events out to every subscriber. Closing the bus drops its senders, which ends every subscriber's receive loop.
+#[derive(Default)]
+pub struct UpdateBus {
+    subscribers: Mutex<Vec<Sender<PoolUpdated>>>,
+}
+
+impl UpdateBus {
+    pub fn new() -> Self {
+        UpdateBus::default()
+    }
+
+    pub fn subscribe(&self) -> Receiver<PoolUpdated> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, event: PoolUpdated) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event).is_ok());
+    }
+
+    pub fn close(&self) {
+        self.subscribers.lock().unwrap().clear();
+    }
+}
+
+struct Search<'a> {
+    pools: Vec<PoolId>,
+    run: Box<dyn FnMut() + 'a>,
+}
+
+// [Scheduler] re-runs only the searches that read a pool named in a batch of events. Each search runs at most once per batch.
+#[derive(Default)]
+pub struct Scheduler<'a> {
+    searches: Vec<Search<'a>>,
+}
+
+impl<'a> Scheduler<'a> {
+    pub fn new() -> Self {
+        Scheduler::default()
+    }
+
+    pub fn register<F: FnMut() + 'a>(&mut self, pools: &[PoolId], search: F) {
+        self.searches.push(Search {
+            pools: pools.to_vec(),
+            run: Box::new(search),
+        });
+    }
+
+    // [dispatch] returns the number of searches that were run.
+    pub fn dispatch(&mut self, events: &[PoolUpdated]) -> usize {
+        let affected: HashSet<PoolId> = events.iter().map(|e| e.pool_id).collect();
+        let mut ran = 0;
+        for search in self.searches.iter_mut() {
+            if search.pools.iter().any(|id| affected.contains(id)) {
+                (search.run)();
+                ran += 1;
+            }
+        }
+        ran
+    }
+
+    // [run] blocks on the receiver, coalescing whatever events are already queued into one dispatch, until the bus is closed.
+    pub fn run(&mut self, receiver: &Receiver<PoolUpdated>) {
+        while let Ok(event) = receiver.recv() {
+            let mut events = vec![event];
+            events.extend(receiver.try_iter());
+            self.dispatch(&events);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn only_affected_searches_run() {
+        let first = Cell::new(0);
+        let second = Cell::new(0);
+        let mut scheduler = Scheduler::new();
+        scheduler.register(&[0, 1], || first.set(first.get() + 1));
+        scheduler.register(&[2], || second.set(second.get() + 1));
+
+        let ran = scheduler.dispatch(&[
+            PoolUpdated {
+                pool_id: 2,
+                version: 1,
+            },
+            PoolUpdated {
+                pool_id: 2,
+                version: 2,
+            },
+        ]);
+
+        assert_eq!(ran, 1);
+        assert_eq!(first.get(), 0);
+        assert_eq!(second.get(), 1);
+    }
+
+    #[test]
+    fn run_stops_when_bus_closes() {
+        let bus = UpdateBus::new();
+        let receiver = bus.subscribe();
+        let runs = Cell::new(0);
+        let mut scheduler = Scheduler::new();
+        scheduler.register(&[0], || runs.set(runs.get() + 1));
+
+        bus.publish(PoolUpdated {
+            pool_id: 0,
+            version: 1,
+        });
+        bus.publish(PoolUpdated {
+            pool_id: 0,
+            version: 2,
+        });
+        bus.close();
+        scheduler.run(&receiver);
+
+        assert_eq!(runs.get(), 1);
+    }
+}
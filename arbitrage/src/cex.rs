@@ -0,0 +1,314 @@
+use crate::gas::GasModel;
+use crate::opportunity::Venue;
+use crate::price_path::PricePath;
+use crate::scenario::{NamedPool, Pool};
+use crate::simulation::{amount_to_price, base_token, spot_price};
+use crate::token::Token;
+use crate::v2;
+use crate::v3::price_to_sqrtp;
+use serde::Serialize;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+// [PriceFeed] gives the latest centralized exchange price, in token_1 per token_0, at a simulated time.
+pub trait PriceFeed {
+    fn price(&mut self, time: u64) -> Option<f64>;
+}
+
+impl PriceFeed for PricePath {
+    fn price(&mut self, time: u64) -> Option<f64> {
+        self.price_at(time)
+    }
+}
+
+// [StreamFeed] is a stub for a live feed: prices pushed into the sender become visible on the next read, which returns the newest one.
+pub struct StreamFeed {
+    receiver: Receiver<f64>,
+    last: Option<f64>,
+}
+
+impl StreamFeed {
+    pub fn new() -> (Sender<f64>, Self) {
+        let (sender, receiver) = channel();
+        (
+            sender,
+            StreamFeed {
+                receiver,
+                last: None,
+            },
+        )
+    }
+}
+
+impl PriceFeed for StreamFeed {
+    fn price(&mut self, _time: u64) -> Option<f64> {
+        if let Some(price) = self.receiver.try_iter().last() {
+            self.last = Some(price);
+        }
+        self.last
+    }
+}
+
+// Both fees are fractions of the hedge's notional. hedge_cost covers everything besides the CEX fee, such as spread and funding.
+#[derive(Copy, Clone, Debug)]
+pub struct CexFees {
+    pub cex_fee: f64,
+    pub hedge_cost: f64,
+}
+
+// [CexDexArb] swaps amount_in on the pool and hedges dex_out back into token_in on the CEX. Costs and profits are in units of token_in.
+#[derive(Clone, Debug, Serialize)]
+pub struct CexDexArb {
+    pub pool: String,
+    pub venue: Venue,
+    pub reference_price: f64,
+    pub target_price: f64,
+    pub token_in: Token,
+    pub amount_in: f64,
+    pub dex_out: f64,
+    pub cex_out: f64,
+    pub cex_fee_cost: f64,
+    pub hedge_cost: f64,
+    pub gas_cost: f64,
+    pub gross_profit: f64,
+    pub net_profit: f64,
+}
+
+// [cex_dex_arb] sizes the swap that moves the pool to the reference price net of the DEX fee, the CEX fee and the hedge cost, and prices the round trip. A V3 trade takes its output and ticks crossed from the same [swap_to_price] walk that sized it. It returns None when the pool is already inside that band or cannot reach it. Gas is converted at the reference price.
+pub fn cex_dex_arb(
+    named: &NamedPool,
+    reference_price: f64,
+    fees: &CexFees,
+    gas: &GasModel,
+) -> Option<CexDexArb> {
+    let pool = &named.pool;
+    let keep = (1. - named.fee) * (1. - fees.cex_fee - fees.hedge_cost);
+    let spot = spot_price(pool);
+    let base = base_token(pool);
+    let quote = base.other();
+    let (token_in, target_price) = if spot * keep > reference_price {
+        (base, reference_price / keep)
+    } else if spot < reference_price * keep {
+        (quote, reference_price * keep)
+    } else {
+        return None;
+    };
+    let (venue, amount_in, dex_out, hop_gas) = match pool {
+        Pool::V2(v2_pool) => {
+            let amount_in = amount_to_price(pool, named.fee, target_price, token_in == base);
+            (
+                Venue::V2,
+                amount_in,
+                v2::quote_amount_out(amount_in, v2_pool, token_in, named.fee),
+                gas.v2_hop_gas(),
+            )
+        }
+        Pool::V3(pool) => {
+            let swap = pool
+                .swap_to_price(price_to_sqrtp(target_price), named.fee)
+                .ok()?;
+            (
+                Venue::V3,
+                swap.amount_in,
+                swap.amount_out,
+                gas.v3_hop_gas(swap.ticks_crossed),
+            )
+        }
+    };
+    if !amount_in.is_finite() || amount_in <= 0. {
+        return None;
+    }
+    let cex_gross = if token_in == base {
+        dex_out / reference_price
+    } else {
+        dex_out * reference_price
+    };
+    let cex_fee_cost = cex_gross * fees.cex_fee;
+    let hedge_cost = cex_gross * fees.hedge_cost;
+    let cex_out = cex_gross - cex_fee_cost - hedge_cost;
+    let reference = v2::Pool::new(base, quote, 1., reference_price);
+    let gas_cost = gas.cost_in_token(gas.tx_gas(hop_gas), token_in, &reference);
+    let gross_profit = cex_out - amount_in;
+    Some(CexDexArb {
+        pool: named.name.clone(),
+        venue,
+        reference_price,
+        target_price,
+        token_in,
+        amount_in,
+        dex_out,
+        cex_out,
+        cex_fee_cost,
+        hedge_cost,
+        gas_cost,
+        gross_profit,
+        net_profit: gross_profit - gas_cost,
+    })
+}
+
+// [CexDexSearcher] reads the feed and returns every pool's CEX-DEX arbitrage that nets more than min_net_profit, most profitable first.
+pub struct CexDexSearcher<F: PriceFeed> {
+    pub feed: F,
+    pub fees: CexFees,
+    pub gas: GasModel,
+    pub min_net_profit: f64,
+}
+
+impl<F: PriceFeed> CexDexSearcher<F> {
+    pub fn search(&mut self, time: u64, pools: &[NamedPool]) -> Vec<CexDexArb> {
+        let reference_price = match self.feed.price(time) {
+            Some(price) => price,
+            None => return vec![],
+        };
+        let mut found: Vec<CexDexArb> = pools
+            .iter()
+            .filter_map(|named| cex_dex_arb(named, reference_price, &self.fees, &self.gas))
+            .filter(|arb| arb.net_profit > self.min_net_profit)
+            .collect();
+        found.sort_by(|a, b| b.net_profit.total_cmp(&a.net_profit));
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::v3;
+
+    fn v2_pool(name: &str, x: f64, y: f64) -> NamedPool {
+        NamedPool {
+            name: name.to_string(),
+            fee: 0.003,
+            pool: Pool::V2(Box::new(v2::Pool::new(Token::Eth, Token::Dai, x, y))),
+        }
+    }
+
+    const FEES: CexFees = CexFees {
+        cex_fee: 0.001,
+        hedge_cost: 0.0005,
+    };
+
+    #[test]
+    fn trade_moves_pool_to_reference_net_of_fees() {
+        let named = v2_pool("pair", 40., 40000.);
+
+        let arb = cex_dex_arb(&named, 1100., &FEES, &GasModel::default()).unwrap();
+
+        assert_eq!(arb.token_in, Token::Dai);
+        assert_eq!(arb.venue, Venue::V2);
+        assert!(arb.net_profit > 0.);
+        assert!(arb.gas_cost > 0.);
+        let moved = v2_pool("moved", 40. - arb.dex_out, 40000. + arb.amount_in * 0.997);
+        assert!((spot_price(&moved.pool) - arb.target_price).abs() < 1e-6);
+        let Pool::V2(pool) = &named.pool else {
+            unreachable!()
+        };
+        let gross = |amount_in: f64| {
+            let out = v2::quote_amount_out(amount_in, pool, Token::Dai, 0.003);
+            out * 1100. * (1. - 0.0015) - amount_in
+        };
+        assert!(gross(arb.amount_in) > gross(arb.amount_in * 0.9));
+        assert!(gross(arb.amount_in) > gross(arb.amount_in * 1.1));
+    }
+
+    #[test]
+    fn no_trade_inside_fee_band() {
+        let named = v2_pool("pair", 40., 40000.);
+
+        assert!(cex_dex_arb(&named, 1003., &FEES, &GasModel::default()).is_none());
+        assert!(cex_dex_arb(&named, 997., &FEES, &GasModel::default()).is_none());
+        assert_eq!(
+            cex_dex_arb(&named, 900., &FEES, &GasModel::default())
+                .unwrap()
+                .token_in,
+            Token::Eth
+        );
+    }
+
+    #[test]
+    fn v3_trade_lands_on_target() {
+        let owner = v3::Trader {
+            id: 1,
+            amt_eth: Default::default(),
+            amt_dai: Default::default(),
+        };
+        let mut pool = v3::uniswap_v3_pool::create(Token::Eth, Token::Dai, 0.003).unwrap();
+        pool.initialize(price_to_sqrtp(5000.)).unwrap();
+        pool.mint(&owner, -86040, 86040, 1000000.).unwrap();
+        pool.mint(&owner, 8520, 8580, 1000000.).unwrap();
+        let named = NamedPool {
+            name: "pool".to_string(),
+            fee: 0.003,
+            pool: Pool::V3(Box::new(pool)),
+        };
+
+        let gas = GasModel::default();
+
+        let arb = cex_dex_arb(&named, 5400., &FEES, &gas).unwrap();
+
+        assert_eq!(arb.venue, Venue::V3);
+        assert_eq!(arb.token_in, Token::Dai);
+        assert!(arb.net_profit > 0.);
+        let reference = v2::Pool::new(Token::Eth, Token::Dai, 1., 5400.);
+        let one_tick = gas.tx_gas(gas.v3_hop_gas(1));
+        assert_eq!(
+            arb.gas_cost,
+            gas.cost_in_token(one_tick, Token::Dai, &reference)
+        );
+        let Pool::V3(pool) = &named.pool else {
+            unreachable!()
+        };
+        let mut trader = v3::Trader {
+            id: 2,
+            amt_eth: Default::default(),
+            amt_dai: Default::default(),
+        };
+        let ticks_crossed =
+            v3::v3_swap(&mut trader, pool, Token::Dai, arb.amount_in, 0.003).unwrap();
+        assert_eq!(ticks_crossed, 1);
+        assert!((trader.balance(Token::Eth) - arb.dex_out).abs() <= arb.dex_out * 1e-9);
+        assert!((spot_price(&named.pool) - arb.target_price).abs() <= arb.target_price * 1e-9);
+    }
+
+    #[test]
+    fn unreachable_reference_price_is_skipped() {
+        let owner = v3::Trader {
+            id: 1,
+            amt_eth: Default::default(),
+            amt_dai: Default::default(),
+        };
+        let mut pool =
+            v3::uniswap_v3_pool::new(Token::Eth, Token::Dai, 5602277097478614198912276234240.);
+        pool.mint(&owner, -86000, 86000, 1e18).unwrap();
+        let named = NamedPool {
+            name: "pool".to_string(),
+            fee: 0.003,
+            pool: Pool::V3(Box::new(pool)),
+        };
+
+        assert!(cex_dex_arb(&named, 0., &FEES, &GasModel::default()).is_none());
+    }
+
+    #[test]
+    fn searcher_uses_latest_streamed_price() {
+        let (sender, feed) = StreamFeed::new();
+        let mut searcher = CexDexSearcher {
+            feed,
+            fees: FEES,
+            gas: GasModel::default(),
+            min_net_profit: 0.,
+        };
+        let pools = vec![v2_pool("small", 4., 4000.), v2_pool("large", 40., 40000.)];
+
+        assert!(searcher.search(0, &pools).is_empty());
+        sender.send(900.).unwrap();
+        sender.send(1000.).unwrap();
+        assert!(searcher.search(12, &pools).is_empty());
+        sender.send(1100.).unwrap();
+        let found = searcher.search(24, &pools);
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].pool, "large");
+        assert!(found[0].net_profit > found[1].net_profit);
+    }
+}
@@ -0,0 +1,857 @@
+use crate::cross::{search_cross_arb, CrossFees, Route};
+use crate::depth::{depth_levels, impact_curve, write_impact_csv};
+use crate::distribution::{liquidity_distribution, render_histogram, write_distribution_csv};
+use crate::flash::FlashError;
+use crate::gas::GasModel;
+use crate::logs::{group_by_block, read_jsonl, LogError};
+use crate::opportunity::{Opportunity, OpportunityConfig};
+use crate::scenario::Pool;
+use crate::snapshot::{Format, PoolSnapshot, Snapshot, SnapshotError};
+use crate::token::{Token, TokenRegistry};
+use crate::v2;
+use crate::v2_logs::V2LogReplayer;
+use crate::v3;
+use crate::v3_logs::V3LogReplayer;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::Instant;
+
+pub const USAGE: &str = "usage: sim <command> [options]
+
+commands:
+  quote     --snapshot FILE --pool N --token-in T --amount X [--fee F]
+  arb       --snapshot FILE --pools A,B --token-in T --max X [--fee F] [--reference N]
+  replay    --snapshot FILE --logs FILE --addresses A,B,... [--out FILE] [--format json|binary]
+  snapshot  --snapshot FILE [--out FILE] [--format json|binary]
+  bench     --snapshot FILE --pools A,B --token-in T --max X [--fee F] [--reference N] [--iterations N]
+  depth     --snapshot FILE --pool N [--fee F]
+  impact    --snapshot FILE --pool N --token-in T --max X [--points N] [--fee F] [--out FILE]
+  liquidity --snapshot FILE --pool N [--window TICKS] [--out FILE] [--format csv|json]
+
+Pools are indexes into the snapshot. Fees are fractions and default to 0.003, except that
+arbitrage between two V3 pools always uses the model's own swap fee. Gas is priced through
+the V2 pool given by --reference, or the first V2 pool in the snapshot. replay takes one
+pool address per snapshot pool, or - to leave a pool alone. impact writes a CSV price impact
+curve to --out, or prints it. liquidity draws a V3 pool's liquidity by tick range around the
+current tick and can export every range to --out. arb settles the opportunity through the
+transfer tax and rebasing rules in --tokens and flags legs that would fall below their minimum.
+
+options:
+  --tokens FILE   JSON token registry used to resolve token names and addresses
+  --json          print JSON instead of text";
+
+const DEFAULT_FEE: f64 = 0.003;
+
+#[derive(Debug)]
+pub enum CliError {
+    Usage(String),
+    Io(io::Error),
+    Logs(LogError),
+    Snapshot(SnapshotError),
+    Flash(FlashError),
+    NoRoute,
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Usage(s) => write!(f, "{}", s),
+            CliError::Io(e) => write!(f, "{}", e),
+            CliError::Logs(e) => write!(f, "{}", e),
+            CliError::Snapshot(e) => write!(f, "{}", e),
+            CliError::Flash(e) => write!(f, "{}", e),
+            CliError::NoRoute => write!(f, "neither route between the pools is profitable"),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> Self {
+        CliError::Io(e)
+    }
+}
+
+impl From<LogError> for CliError {
+    fn from(e: LogError) -> Self {
+        CliError::Logs(e)
+    }
+}
+
+impl From<SnapshotError> for CliError {
+    fn from(e: SnapshotError) -> Self {
+        CliError::Snapshot(e)
+    }
+}
+
+impl From<FlashError> for CliError {
+    fn from(e: FlashError) -> Self {
+        CliError::Flash(e)
+    }
+}
+
+fn usage<T>(message: String) -> Result<T, CliError> {
+    Err(CliError::Usage(message))
+}
+
+struct Args {
+    command: String,
+    options: HashMap<String, String>,
+    json: bool,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Args, CliError> {
+        let mut iter = args.iter();
+        let command = match iter.next() {
+            Some(command) => command.clone(),
+            None => return usage("no command given".to_string()),
+        };
+        let mut options = HashMap::new();
+        let mut json = false;
+        while let Some(arg) = iter.next() {
+            let name = match arg.strip_prefix("--") {
+                Some(name) => name,
+                None => return usage(format!("unexpected argument {:?}", arg)),
+            };
+            if name == "json" {
+                json = true;
+                continue;
+            }
+            match iter.next() {
+                Some(value) => options.insert(name.to_string(), value.clone()),
+                None => return usage(format!("--{} needs a value", name)),
+            };
+        }
+        Ok(Args {
+            command,
+            options,
+            json,
+        })
+    }
+
+    fn get(&self, name: &str) -> Result<&str, CliError> {
+        match self.options.get(name) {
+            Some(value) => Ok(value),
+            None => usage(format!("{} needs --{}", self.command, name)),
+        }
+    }
+
+    fn number<T: FromStr>(&self, name: &str) -> Result<T, CliError> {
+        let value = self.get(name)?;
+        value
+            .parse()
+            .or_else(|_| usage(format!("--{} {:?} is not a number", name, value)))
+    }
+
+    // [amount] is a number option that must be positive and finite, such as a maximum trade size.
+    fn amount(&self, name: &str) -> Result<f64, CliError> {
+        let value: f64 = self.number(name)?;
+        if !(value.is_finite() && value > 0.) {
+            return usage(format!(
+                "--{} must be positive and finite, got {}",
+                name, value
+            ));
+        }
+        Ok(value)
+    }
+
+    fn number_or<T: FromStr>(&self, name: &str, default: T) -> Result<T, CliError> {
+        if self.options.contains_key(name) {
+            self.number(name)
+        } else {
+            Ok(default)
+        }
+    }
+
+    fn format(&self) -> Result<Format, CliError> {
+        match self.options.get("format").map(String::as_str) {
+            None | Some("json") => Ok(Format::Json),
+            Some("binary") => Ok(Format::Binary),
+            Some(other) => usage(format!("unknown format {:?}", other)),
+        }
+    }
+}
+
+// [Session] is everything a command reads from files: the snapshot, its pools and the token registry.
+struct Session {
+    snapshot: Snapshot,
+    pools: Vec<Pool>,
+    tokens: TokenRegistry,
+}
+
+impl Session {
+    fn open(args: &Args) -> Result<Session, CliError> {
+        let snapshot = Snapshot::load(args.get("snapshot")?)?;
+        let pools = snapshot
+            .pools
+            .iter()
+            .map(|pool| match pool {
+                PoolSnapshot::V2(p) => Pool::V2(Box::new(p.to_pool())),
+                PoolSnapshot::V3(p) => Pool::V3(Box::new(p.to_pool())),
+            })
+            .collect();
+        let tokens = match args.options.get("tokens") {
+            Some(path) => TokenRegistry::load(path)?,
+            None => TokenRegistry::default(),
+        };
+        Ok(Session {
+            snapshot,
+            pools,
+            tokens,
+        })
+    }
+
+    fn pool(&self, index: usize) -> Result<&Pool, CliError> {
+        match self.pools.get(index) {
+            Some(pool) => Ok(pool),
+            None => usage(format!(
+                "pool {} is not in the snapshot, which has {} pools",
+                index,
+                self.pools.len()
+            )),
+        }
+    }
+
+    fn pool_arg(&self, args: &Args, name: &str) -> Result<&Pool, CliError> {
+        self.pool(args.number(name)?)
+    }
+
+    fn token(&self, args: &Args, name: &str) -> Result<Token, CliError> {
+        self.tokens.resolve(args.get(name)?).or_else(usage)
+    }
+
+    fn reference(&self, args: &Args) -> Result<&v2::Pool, CliError> {
+        let found = match args.options.get("reference") {
+            Some(_) => match self.pool_arg(args, "reference")? {
+                Pool::V2(pool) => Some(pool),
+                Pool::V3(_) => return usage("--reference must be a V2 pool".to_string()),
+            },
+            None => self.pools.iter().find_map(|pool| match pool {
+                Pool::V2(pool) => Some(pool),
+                Pool::V3(_) => None,
+            }),
+        };
+        match found {
+            Some(pool) => Ok(pool),
+            None => usage("no V2 pool to price gas with, pass --reference".to_string()),
+        }
+    }
+
+    fn save(&self, args: &Args, block_number: u64) -> Result<Option<String>, CliError> {
+        let path = match args.options.get("out") {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let mut snapshot = Snapshot::new(block_number);
+        for pool in &self.pools {
+            match pool {
+                Pool::V2(pool) => snapshot.push_v2(pool),
+                Pool::V3(pool) => snapshot.push_v3(pool),
+            }
+        }
+        snapshot.save(path, args.format()?)?;
+        Ok(Some(path.clone()))
+    }
+}
+
+// [run] executes one command line, without the program name, and writes its output to out.
+pub fn run(args: &[String], out: &mut dyn Write) -> Result<(), CliError> {
+    let args = Args::parse(args)?;
+    match args.command.as_str() {
+        "quote" => quote(&args, out),
+        "arb" => arb(&args, out),
+        "replay" => replay(&args, out),
+        "snapshot" => snapshot(&args, out),
+        "bench" => bench(&args, out),
+        "depth" => depth(&args, out),
+        "impact" => impact(&args, out),
+        "liquidity" => liquidity(&args, out),
+        "help" => Ok(writeln!(out, "{}", USAGE)?),
+        other => usage(format!("unknown command {:?}", other)),
+    }
+}
+
+fn quote(args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    let session = Session::open(args)?;
+    let token_in = session.token(args, "token-in")?;
+    let amount_in: f64 = args.number("amount")?;
+    let fee = args.number_or("fee", DEFAULT_FEE)?;
+    let amount_out = match session.pool_arg(args, "pool")? {
+        Pool::V2(pool) => v2::quote_amount_out(amount_in, pool, token_in, fee),
+        Pool::V3(pool) => v3::v3_quote(pool, token_in, amount_in, fee),
+    };
+    if args.json {
+        let report = json!({
+            "token_in": token_in,
+            "amount_in": amount_in,
+            "token_out": token_in.other(),
+            "amount_out": amount_out,
+        });
+        writeln!(out, "{}", report)?;
+    } else {
+        writeln!(
+            out,
+            "{} {:?} -> {} {:?}",
+            amount_in,
+            token_in,
+            amount_out,
+            token_in.other()
+        )?;
+    }
+    Ok(())
+}
+
+// [LegPools] is the pool and swap fee each leg of a found opportunity trades against, in leg order.
+type LegPools<'a> = Vec<(&'a Pool, f64)>;
+
+// [search] sizes and packages the arbitrage between two snapshot pools, picking the V2, V3 or cross-protocol search by pool kind.
+fn search<'a>(
+    session: &'a Session,
+    args: &Args,
+) -> Result<(Opportunity, Option<Route>, LegPools<'a>), CliError> {
+    let pools = args.get("pools")?;
+    let indexes: Vec<&str> = pools.split(',').collect();
+    if indexes.len() != 2 {
+        return usage(format!("--pools takes two pools, got {:?}", pools));
+    }
+    let mut pair = vec![];
+    for index in indexes {
+        match index.trim().parse() {
+            Ok(index) => pair.push(session.pool(index)?),
+            Err(_) => return usage(format!("{:?} is not a pool index", index)),
+        }
+    }
+    let token_in = session.token(args, "token-in")?;
+    let max_amt_in = args.amount("max")?;
+    let fee = args.number_or("fee", DEFAULT_FEE)?;
+    let reference = session.reference(args)?;
+    let gas = GasModel::default();
+    let config = OpportunityConfig::default();
+    let found = match (pair[0], pair[1]) {
+        (Pool::V3(pool1), Pool::V3(pool2)) => {
+            let amount_in =
+                v3::find_optimal_arb(pool1, pool2, token_in, max_amt_in, &gas, reference);
+            let opportunity = v3::two_pool_opportunity(
+                amount_in, pool1, pool2, token_in, &gas, reference, &config,
+            )?;
+            (
+                opportunity,
+                None,
+                vec![(pair[0], v3::ARB_FEE), (pair[1], v3::ARB_FEE)],
+            )
+        }
+        (Pool::V2(pool1), Pool::V2(pool2)) => {
+            let multiplier = 1. - fee;
+            let amount_in = v2::find_optimal_arb(
+                pool1, pool2, token_in, multiplier, max_amt_in, &gas, reference,
+            );
+            let opportunity = v2::two_pool_opportunity(
+                pool1, pool2, token_in, multiplier, amount_in, &gas, reference, &config,
+            );
+            (opportunity, None, vec![(pair[0], fee), (pair[1], fee)])
+        }
+        (Pool::V2(v2_pool), Pool::V3(v3_pool)) | (Pool::V3(v3_pool), Pool::V2(v2_pool)) => {
+            let fees = CrossFees {
+                v2_fee: fee,
+                v3_fee: fee,
+            };
+            let arb = search_cross_arb(
+                v2_pool, v3_pool, token_in, &fees, max_amt_in, &gas, reference, &config,
+            )
+            .ok_or(CliError::NoRoute)?;
+            let (v2_leg, v3_leg) = match pair[0] {
+                Pool::V2(_) => (pair[0], pair[1]),
+                Pool::V3(_) => (pair[1], pair[0]),
+            };
+            let legs = match arb.route {
+                Route::V2ThenV3 => vec![(v2_leg, fees.v2_fee), (v3_leg, fees.v3_fee)],
+                Route::V3ThenV2 => vec![(v3_leg, fees.v3_fee), (v2_leg, fees.v2_fee)],
+            };
+            (arb.opportunity, Some(arb.route), legs)
+        }
+    };
+    Ok(found)
+}
+
+fn arb(args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    let session = Session::open(args)?;
+    let (quoted, route, pools) = search(&session, args)?;
+    let settlement = quoted.settle(&pools, &session.tokens);
+    let opportunity = &settlement.opportunity;
+    let accepted = settlement.is_safe() && OpportunityConfig::default().accepts(opportunity);
+    if args.json {
+        let report = json!({
+            "route": route,
+            "accepted": accepted,
+            "opportunity": opportunity,
+            "unsafe_legs": settlement.unsafe_legs,
+        });
+        writeln!(out, "{}", report)?;
+        return Ok(());
+    }
+    if let Some(route) = route {
+        writeln!(out, "route:        {:?}", route)?;
+    }
+    writeln!(
+        out,
+        "amount in:    {} {:?}",
+        opportunity.amount_in, opportunity.token_in
+    )?;
+    writeln!(out, "gross profit: {}", opportunity.gross_profit)?;
+    writeln!(out, "gas:          {}", opportunity.gas_cost)?;
+    writeln!(out, "net profit:   {}", opportunity.net_profit)?;
+    writeln!(out, "accepted:     {}", accepted)?;
+    if !settlement.is_safe() {
+        writeln!(
+            out,
+            "unsafe:       legs {:?} deliver less than their minimum after token transfer rules",
+            settlement
+                .unsafe_legs
+                .iter()
+                .map(|i| i + 1)
+                .collect::<Vec<_>>()
+        )?;
+    }
+    for (i, leg) in opportunity.legs.iter().enumerate() {
+        writeln!(
+            out,
+            "leg {}: {:?} {} {:?} -> {} (min {})",
+            i + 1,
+            leg.venue,
+            leg.amount_in,
+            leg.token_in,
+            leg.expected_out,
+            leg.min_out
+        )?;
+    }
+    Ok(())
+}
+
+enum Replayer {
+    V2(V2LogReplayer),
+    V3(V3LogReplayer),
+    Skip,
+}
+
+fn replay(args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    let mut session = Session::open(args)?;
+    let logs = read_jsonl(args.get("logs")?)?;
+    let addresses: Vec<&str> = args.get("addresses")?.split(',').map(str::trim).collect();
+    if addresses.len() != session.pools.len() {
+        return usage(format!(
+            "--addresses has {} entries but the snapshot has {} pools",
+            addresses.len(),
+            session.pools.len()
+        ));
+    }
+    let mut replayers: Vec<Replayer> = addresses
+        .iter()
+        .zip(session.pools.iter())
+        .map(|(address, pool)| match (*address, pool) {
+            ("-", _) => Replayer::Skip,
+            (address, Pool::V2(_)) => Replayer::V2(V2LogReplayer::new(address)),
+            (address, Pool::V3(_)) => Replayer::V3(V3LogReplayer::new(address)),
+        })
+        .collect();
+
+    let mut block_number = session.snapshot.block_number;
+    let mut blocks = 0;
+    let mut events = 0;
+    for (number, block) in group_by_block(&logs) {
+        if number <= session.snapshot.block_number {
+            continue;
+        }
+        for log in block {
+            for (replayer, pool) in replayers.iter_mut().zip(session.pools.iter_mut()) {
+                let applied = match (replayer, pool) {
+                    (Replayer::V2(replayer), Pool::V2(pool)) => replayer.apply_log(pool, log)?,
+                    (Replayer::V3(replayer), Pool::V3(pool)) => replayer.apply_log(pool, log)?,
+                    _ => false,
+                };
+                if applied {
+                    events += 1;
+                }
+            }
+        }
+        block_number = number;
+        blocks += 1;
+    }
+
+    let saved = session.save(args, block_number)?;
+    if args.json {
+        let report = json!({
+            "blocks": blocks,
+            "events": events,
+            "block_number": block_number,
+            "saved": saved,
+        });
+        writeln!(out, "{}", report)?;
+    } else {
+        writeln!(
+            out,
+            "replayed {} events over {} blocks, now at block {}",
+            events, blocks, block_number
+        )?;
+        if let Some(path) = saved {
+            writeln!(out, "saved snapshot to {}", path)?;
+        }
+    }
+    Ok(())
+}
+
+fn snapshot(args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    let session = Session::open(args)?;
+    let saved = session.save(args, session.snapshot.block_number)?;
+    if args.json {
+        writeln!(out, "{}", session.snapshot.to_json()?)?;
+        return Ok(());
+    }
+    writeln!(
+        out,
+        "snapshot version {} at block {}",
+        session.snapshot.version, session.snapshot.block_number
+    )?;
+    for (i, pool) in session.snapshot.pools.iter().enumerate() {
+        match pool {
+            PoolSnapshot::V2(p) => writeln!(
+                out,
+                "pool {}: V2 {:?}/{:?} reserves {} / {}",
+                i, p.token_x, p.token_y, p.x, p.y
+            )?,
+            PoolSnapshot::V3(p) => writeln!(
+                out,
+                "pool {}: V3 {:?}/{:?} tick {} sqrt price {} liquidity {}, {} ticks, {} positions",
+                i,
+                p.token_0,
+                p.token_1,
+                p.tick,
+                p.sqrt_price_x96,
+                p.liquidity,
+                p.ticks.len(),
+                p.positions.len()
+            )?,
+        }
+    }
+    if let Some(path) = saved {
+        writeln!(out, "saved snapshot to {}", path)?;
+    }
+    Ok(())
+}
+
+fn bench(args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    let session = Session::open(args)?;
+    let iterations: u32 = args.number_or("iterations", 10)?;
+    if iterations == 0 {
+        return usage("--iterations must be at least 1".to_string());
+    }
+    let start = Instant::now();
+    let mut amount_in = 0.;
+    for _ in 0..iterations {
+        amount_in = search(&session, args)?.0.amount_in;
+    }
+    let mean_ms = start.elapsed().as_secs_f64() * 1000. / iterations as f64;
+    if args.json {
+        let report = json!({
+            "iterations": iterations,
+            "mean_ms": mean_ms,
+            "amount_in": amount_in,
+        });
+        writeln!(out, "{}", report)?;
+    } else {
+        writeln!(
+            out,
+            "{} searches, {:.3} ms each, optimal amount in {}",
+            iterations, mean_ms, amount_in
+        )?;
+    }
+    Ok(())
+}
+
+fn depth(args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    let session = Session::open(args)?;
+    let fee = args.number_or("fee", DEFAULT_FEE)?;
+    let levels = depth_levels(session.pool_arg(args, "pool")?, fee);
+    if args.json {
+        writeln!(out, "{}", json!(levels))?;
+        return Ok(());
+    }
+    writeln!(out, "impact  base in  quote in")?;
+    for level in levels {
+        writeln!(
+            out,
+            "{:>5}%  {}  {}",
+            level.impact * 100.,
+            level.base_in,
+            level.quote_in
+        )?;
+    }
+    Ok(())
+}
+
+fn impact(args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    let session = Session::open(args)?;
+    let token_in = session.token(args, "token-in")?;
+    let max_amount = args.amount("max")?;
+    let points: usize = args.number_or("points", 20)?;
+    if points == 0 {
+        return usage("--points must be at least 1".to_string());
+    }
+    let fee = args.number_or("fee", DEFAULT_FEE)?;
+    let curve = impact_curve(
+        session.pool_arg(args, "pool")?,
+        fee,
+        token_in,
+        max_amount,
+        points,
+    );
+    if let Some(path) = args.options.get("out") {
+        let mut file = fs::File::create(path)?;
+        write_impact_csv(&curve, &mut file)?;
+        if args.json {
+            writeln!(out, "{}", json!({ "points": points, "saved": path }))?;
+        } else {
+            writeln!(out, "wrote {} points to {}", points, path)?;
+        }
+    } else if args.json {
+        writeln!(out, "{}", json!(curve))?;
+    } else {
+        write_impact_csv(&curve, out)?;
+    }
+    Ok(())
+}
+
+fn liquidity(args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    let session = Session::open(args)?;
+    let pool = match session.pool_arg(args, "pool")? {
+        Pool::V3(pool) => pool,
+        Pool::V2(_) => return usage("liquidity needs a V3 pool".to_string()),
+    };
+    let window: i32 = args.number_or("window", 1000)?;
+    let ranges = liquidity_distribution(pool);
+    if let Some(path) = args.options.get("out") {
+        let mut file = fs::File::create(path)?;
+        match args.options.get("format").map(String::as_str) {
+            None | Some("csv") => write_distribution_csv(&ranges, &mut file)?,
+            Some("json") => writeln!(file, "{}", json!(ranges))?,
+            Some(other) => return usage(format!("unknown format {:?}", other)),
+        }
+    }
+    if args.json {
+        writeln!(out, "{}", json!(ranges))?;
+    } else {
+        let tick = *pool.tick.read().unwrap();
+        writeln!(out, "tick {}, {} ranges", tick, ranges.len())?;
+        write!(out, "{}", render_histogram(&ranges, tick, window, 40))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::v2_logs::SYNC_TOPIC;
+    use serde_json::Value;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::RwLock;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("arbitrage-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    fn write_snapshot(name: &str) -> String {
+        let mut snapshot = Snapshot::new(100);
+        for y in [3500., 4000.] {
+            snapshot.push_v2(&v2::Pool::new(Token::Eth, Token::Dai, 4., y));
+        }
+        let path = scratch(name);
+        snapshot.save(&path, Format::Json).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn run_json(line: &str) -> Value {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        let mut out = vec![];
+        run(&args, &mut out).unwrap();
+        serde_json::from_slice(&out).unwrap()
+    }
+
+    #[test]
+    fn quotes_through_registry_symbol() {
+        let snapshot = write_snapshot("quote.json");
+        let tokens = scratch("tokens.json");
+        fs::write(
+            &tokens,
+            r#"[{"symbol":"WETH","address":"0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2","token":"Eth"}]"#,
+        )
+        .unwrap();
+
+        let report = run_json(&format!(
+            "quote --snapshot {} --tokens {} --pool 1 --token-in weth --amount 1 --fee 0 --json",
+            snapshot,
+            tokens.display()
+        ));
+
+        assert_eq!(report["token_out"], "Dai");
+        assert_eq!(report["amount_out"], 800.);
+    }
+
+    #[test]
+    fn arb_reports_opportunity() {
+        let snapshot = write_snapshot("arb.json");
+
+        let report = run_json(&format!(
+            "arb --snapshot {} --pools 0,1 --token-in eth --max 2 --fee 0.03 --json",
+            snapshot
+        ));
+
+        assert_eq!(report["route"], Value::Null);
+        assert_eq!(report["opportunity"]["legs"].as_array().unwrap().len(), 2);
+        assert!(report["opportunity"]["amount_in"].as_f64().unwrap() > 0.);
+    }
+
+    #[test]
+    fn arb_flags_taxed_route() {
+        let snapshot = write_snapshot("arb_taxed.json");
+        let tokens = scratch("taxed_tokens.json");
+        fs::write(
+            &tokens,
+            r#"[{"symbol":"TAX","address":"0x01","token":"Dai","behavior":{"transfer_tax_bps":500,"rebase_multiplier":1.0}}]"#,
+        )
+        .unwrap();
+
+        let report = run_json(&format!(
+            "arb --snapshot {} --tokens {} --pools 0,1 --token-in eth --max 2 --fee 0.03 --json",
+            snapshot,
+            tokens.display()
+        ));
+
+        assert!(report["unsafe_legs"]
+            .as_array()
+            .unwrap()
+            .contains(&json!(1)));
+        assert_eq!(report["accepted"], false);
+        let legs = report["opportunity"]["legs"].as_array().unwrap();
+        assert!(legs[1]["expected_out"].as_f64() < legs[1]["min_out"].as_f64());
+    }
+
+    #[test]
+    fn replay_advances_snapshot() {
+        let snapshot = write_snapshot("replay.json");
+        let logs = scratch("logs.jsonl");
+        let out = scratch("replayed.bin");
+        fs::write(
+            &logs,
+            format!(
+                r#"{{"address":"0xab","topics":["{}"],"data":"0x{:064x}{:064x}","blockNumber":"0x65","logIndex":"0x0"}}"#,
+                SYNC_TOPIC, 5, 3000
+            ),
+        )
+        .unwrap();
+
+        let report = run_json(&format!(
+            "replay --snapshot {} --logs {} --addresses 0xAB,- --out {} --format binary --json",
+            snapshot,
+            logs.display(),
+            out.display()
+        ));
+
+        assert_eq!(report["events"], 1);
+        assert_eq!(report["block_number"], 101);
+        let replayed = Snapshot::load(&out).unwrap();
+        assert_eq!(replayed.block_number, 101);
+        let PoolSnapshot::V2(pool) = &replayed.pools[0] else {
+            panic!("expected a V2 pool");
+        };
+        assert_eq!((pool.x, pool.y), (5., 3000.));
+    }
+
+    #[test]
+    fn exports_depth_and_impact_curve() {
+        let snapshot = write_snapshot("depth.json");
+        let csv = scratch("impact.csv");
+
+        let levels = run_json(&format!("depth --snapshot {} --pool 0 --json", snapshot));
+        run_json(&format!(
+            "impact --snapshot {} --pool 0 --token-in eth --max 2 --points 4 --out {} --json",
+            snapshot,
+            csv.display()
+        ));
+
+        assert_eq!(levels.as_array().unwrap().len(), 3);
+        assert_eq!(levels[1]["impact"], 0.01);
+        assert!(levels[0]["base_in"].as_f64().unwrap() > 0.);
+        let written = fs::read_to_string(&csv).unwrap();
+        assert_eq!(written.lines().count(), 5);
+        assert!(written.starts_with("amount_in,amount_out,effective_price,marginal_price"));
+    }
+
+    #[test]
+    fn draws_liquidity_histogram() {
+        let mut pool =
+            v3::uniswap_v3_pool::new(Token::Eth, Token::Dai, 5602277097478614198912276234240.);
+        let owner = v3::Trader {
+            id: 2,
+            amt_eth: RwLock::new(0.),
+            amt_dai: RwLock::new(0.),
+        };
+        pool.mint(&owner, 8400, 8800, 100.).unwrap();
+        let mut snapshot = Snapshot::new(1);
+        snapshot.push_v3(&pool);
+        let path = scratch("liquidity.json");
+        snapshot.save(&path, Format::Json).unwrap();
+        let exported = scratch("liquidity.csv");
+
+        let args: Vec<String> = format!(
+            "liquidity --snapshot {} --pool 0 --out {}",
+            path.display(),
+            exported.display()
+        )
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+        let mut out = vec![];
+        run(&args, &mut out).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("tick 8521, 1 ranges"));
+        assert!(text.contains("8400..8800 |"));
+        assert!(text.contains("<- tick"));
+        let csv = fs::read_to_string(&exported).unwrap();
+        assert_eq!(csv, "lower_tick,upper_tick,liquidity\n8400,8800,100\n");
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let mut out = vec![];
+        let unknown = run(&["trade".to_string()], &mut out);
+        let missing = run(&["quote".to_string()], &mut out);
+
+        assert!(matches!(unknown, Err(CliError::Usage(_))));
+        assert!(matches!(missing, Err(CliError::Usage(m)) if m == "quote needs --snapshot"));
+
+        let snapshot = write_snapshot("arb_max.json");
+        for max in ["0", "-2", "inf", "NaN"] {
+            let args: Vec<String> = format!(
+                "arb --snapshot {} --pools 0,1 --token-in eth --max {}",
+                snapshot, max
+            )
+            .split_whitespace()
+            .map(String::from)
+            .collect();
+            assert!(matches!(
+                run(&args, &mut out),
+                Err(CliError::Usage(m)) if m.starts_with("--max must be positive and finite")
+            ));
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use crate::gas::GasModel;
+use crate::opportunity::{Opportunity, OpportunityConfig, Venue};
+use crate::token::Token;
+use crate::v2;
+use crate::v3;
+use serde::Serialize;
+
+const SEARCH_STEPS: i32 = 1000;
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize)]
+pub enum Route {
+    V2ThenV3,
+    V3ThenV2,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CrossArb {
+    pub route: Route,
+    pub opportunity: Opportunity,
+}
+
+pub struct CrossFees {
+    pub v2_fee: f64,
+    pub v3_fee: f64,
+}
+
+fn cross_arb(
+    x_in: f64,
+    v2_pool: &v2::Pool,
+    v3_pool: &v3::uniswap_v3_pool,
+    token_in: Token,
+    route: Route,
+    fees: &CrossFees,
+) -> (f64, f64, i32) {
+    let (mid, amount_out, ticks_crossed) = match route {
+        Route::V2ThenV3 => {
+            let mid = v2::quote_amount_out(x_in, v2_pool, token_in, fees.v2_fee);
+            let (amount_out, ticks_crossed) =
+                v3::v3_quote_with_ticks(v3_pool, token_in.other(), mid, fees.v3_fee);
+            (mid, amount_out, ticks_crossed)
+        }
+        Route::V3ThenV2 => {
+            let (mid, ticks_crossed) =
+                v3::v3_quote_with_ticks(v3_pool, token_in, x_in, fees.v3_fee);
+            (
+                mid,
+                v2::quote_amount_out(mid, v2_pool, token_in.other(), fees.v2_fee),
+                ticks_crossed,
+            )
+        }
+    };
+    (mid, amount_out - x_in, ticks_crossed)
+}
+
+fn cross_arb_gas_cost(
+    ticks_crossed: i32,
+    token_in: Token,
+    gas: &GasModel,
+    reference: &v2::Pool,
+) -> f64 {
+    let gas_units = gas.tx_gas(gas.v2_hop_gas() + gas.v3_hop_gas(ticks_crossed));
+    gas.cost_in_token(gas_units, token_in, reference)
+}
+
+// [calc_cross_arb_profit] sends x_in of token_in through both pools in the order given by route and returns what comes back less x_in. Neither pool is modified.
+pub fn calc_cross_arb_profit(
+    x_in: f64,
+    v2_pool: &v2::Pool,
+    v3_pool: &v3::uniswap_v3_pool,
+    token_in: Token,
+    route: Route,
+    fees: &CrossFees,
+) -> f64 {
+    cross_arb(x_in, v2_pool, v3_pool, token_in, route, fees).1
+}
+
+// [calc_cross_arb_net_profit] is [calc_cross_arb_profit] less the gas for one V2 hop and one V3 hop, in units of token_in.
+#[allow(clippy::too_many_arguments)]
+pub fn calc_cross_arb_net_profit(
+    x_in: f64,
+    v2_pool: &v2::Pool,
+    v3_pool: &v3::uniswap_v3_pool,
+    token_in: Token,
+    route: Route,
+    fees: &CrossFees,
+    gas: &GasModel,
+    reference: &v2::Pool,
+) -> f64 {
+    let (_, profit, ticks_crossed) = cross_arb(x_in, v2_pool, v3_pool, token_in, route, fees);
+    profit - cross_arb_gas_cost(ticks_crossed, token_in, gas, reference)
+}
+
+// [find_optimal_cross_arb] tries SEARCH_STEPS evenly spaced sizes up to max_amt_in and returns the one with the highest net profit. A max_amt_in that is not positive and finite gives no sizes to try, and zero is returned.
+#[allow(clippy::too_many_arguments)]
+pub fn find_optimal_cross_arb(
+    v2_pool: &v2::Pool,
+    v3_pool: &v3::uniswap_v3_pool,
+    token_in: Token,
+    route: Route,
+    fees: &CrossFees,
+    max_amt_in: f64,
+    gas: &GasModel,
+    reference: &v2::Pool,
+) -> f64 {
+    let step = max_amt_in / SEARCH_STEPS as f64;
+    if !(step > 0. && step.is_finite()) {
+        return 0.;
+    }
+    let mut amt = step;
+    let mut max_out = f64::MIN;
+    let mut opt_amt = 0.;
+    while amt <= max_amt_in {
+        let amt_out =
+            calc_cross_arb_net_profit(amt, v2_pool, v3_pool, token_in, route, fees, gas, reference);
+        if amt_out > max_out {
+            max_out = amt_out;
+            opt_amt = amt;
+        }
+        amt += step;
+    }
+    opt_amt
+}
+
+// [cross_opportunity] quotes both legs of the route for x_in and packages them with gas and per-leg minimum outputs.
+#[allow(clippy::too_many_arguments)]
+pub fn cross_opportunity(
+    x_in: f64,
+    v2_pool: &v2::Pool,
+    v3_pool: &v3::uniswap_v3_pool,
+    token_in: Token,
+    route: Route,
+    fees: &CrossFees,
+    gas: &GasModel,
+    reference: &v2::Pool,
+    config: &OpportunityConfig,
+) -> Opportunity {
+    let (mid, profit, ticks_crossed) = cross_arb(x_in, v2_pool, v3_pool, token_in, route, fees);
+    let gas_cost = cross_arb_gas_cost(ticks_crossed, token_in, gas, reference);
+    let (first, second) = match route {
+        Route::V2ThenV3 => (Venue::V2, Venue::V3),
+        Route::V3ThenV2 => (Venue::V3, Venue::V2),
+    };
+    Opportunity {
+        token_in,
+        amount_in: x_in,
+        gross_profit: profit,
+        gas_cost,
+        net_profit: profit - gas_cost,
+        legs: vec![
+            config.leg(first, token_in, x_in, mid),
+            config.leg(second, token_in.other(), mid, x_in + profit),
+        ],
+    }
+}
+
+// [search_cross_arb] quotes both routes for token_in and returns the one with the higher net profit, sized by find_optimal_cross_arb, or None when neither route makes money net of gas.
+#[allow(clippy::too_many_arguments)]
+pub fn search_cross_arb(
+    v2_pool: &v2::Pool,
+    v3_pool: &v3::uniswap_v3_pool,
+    token_in: Token,
+    fees: &CrossFees,
+    max_amt_in: f64,
+    gas: &GasModel,
+    reference: &v2::Pool,
+    config: &OpportunityConfig,
+) -> Option<CrossArb> {
+    [Route::V2ThenV3, Route::V3ThenV2]
+        .iter()
+        .map(|&route| {
+            let amount_in = find_optimal_cross_arb(
+                v2_pool, v3_pool, token_in, route, fees, max_amt_in, gas, reference,
+            );
+            CrossArb {
+                route,
+                opportunity: cross_opportunity(
+                    amount_in, v2_pool, v3_pool, token_in, route, fees, gas, reference, config,
+                ),
+            }
+        })
+        .filter(|arb| arb.opportunity.net_profit > 0.)
+        .fold(None, |best: Option<CrossArb>, arb| match best {
+            Some(b) if b.opportunity.net_profit >= arb.opportunity.net_profit => Some(b),
+            _ => Some(arb),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::sync::RwLock;
+
+    fn set_up_pools(v2_eth: f64, v2_dai: f64) -> (v2::Pool, v3::uniswap_v3_pool) {
+        let trader = v3::Trader {
+            id: 2,
+            amt_eth: RwLock::new(0.),
+            amt_dai: RwLock::new(0.),
+        };
+        let mut v3_pool =
+            v3::uniswap_v3_pool::new(Token::Eth, Token::Dai, 5602277097478614198912276234240.);
+        v3_pool.mint(&trader, -86000, 86000, 1000000.).unwrap();
+        let v2_pool = v2::Pool::new(Token::Eth, Token::Dai, v2_eth, v2_dai);
+        (v2_pool, v3_pool)
+    }
+
+    #[test]
+    fn finds_profitable_route() {
+        let (v2_pool, v3_pool) = set_up_pools(1000., 4000000.);
+        let fees = CrossFees {
+            v2_fee: 0.003,
+            v3_fee: 0.003,
+        };
+
+        let gas = GasModel::default();
+
+        let arb = search_cross_arb(
+            &v2_pool,
+            &v3_pool,
+            Token::Dai,
+            &fees,
+            1000000.,
+            &gas,
+            &v2_pool,
+            &OpportunityConfig::default(),
+        )
+        .unwrap();
+        let opportunity = &arb.opportunity;
+
+        assert_eq!(arb.route, Route::V2ThenV3);
+        assert_eq!(opportunity.legs[0].venue, Venue::V2);
+        assert_eq!(opportunity.legs[1].venue, Venue::V3);
+        assert!(opportunity.amount_in > 0.);
+        assert!(opportunity.gross_profit > 0.);
+        assert!(opportunity.gas_cost > 0.);
+        assert_eq!(
+            opportunity.net_profit,
+            opportunity.gross_profit - opportunity.gas_cost
+        );
+        assert_eq!(*v2_pool.x.read().unwrap(), 1000.);
+    }
+
+    #[test]
+    fn optimal_size_beats_neighbours() {
+        let (v2_pool, v3_pool) = set_up_pools(1000., 4000000.);
+        let fees = CrossFees {
+            v2_fee: 0.003,
+            v3_fee: 0.003,
+        };
+
+        let gas = GasModel::default();
+
+        let amt = find_optimal_cross_arb(
+            &v2_pool,
+            &v3_pool,
+            Token::Dai,
+            Route::V2ThenV3,
+            &fees,
+            1000000.,
+            &gas,
+            &v2_pool,
+        );
+        let profit = |x_in: f64| {
+            calc_cross_arb_net_profit(
+                x_in,
+                &v2_pool,
+                &v3_pool,
+                Token::Dai,
+                Route::V2ThenV3,
+                &fees,
+                &gas,
+                &v2_pool,
+            )
+        };
+
+        assert!(profit(amt) >= profit(amt / 2.));
+        assert!(profit(amt) >= profit(amt * 1.5));
+    }
+
+    #[test]
+    fn no_route_without_profit() {
+        let (v2_pool, v3_pool) = set_up_pools(1000., 5000000.);
+        let fees = CrossFees {
+            v2_fee: 0.003,
+            v3_fee: 0.003,
+        };
+        let gas = GasModel::default();
+        let config = OpportunityConfig::default();
+
+        for token_in in [Token::Eth, Token::Dai] {
+            assert!(search_cross_arb(
+                &v2_pool, &v3_pool, token_in, &fees, 1000000., &gas, &v2_pool, &config,
+            )
+            .is_none());
+        }
+        for max_amt_in in [0., -1., f64::NAN, f64::INFINITY] {
+            assert_eq!(
+                find_optimal_cross_arb(
+                    &v2_pool,
+                    &v3_pool,
+                    Token::Dai,
+                    Route::V2ThenV3,
+                    &fees,
+                    max_amt_in,
+                    &gas,
+                    &v2_pool,
+                ),
+                0.
+            );
+        }
+    }
+}
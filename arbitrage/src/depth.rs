@@ -0,0 +1,189 @@
+use crate::scenario::Pool;
+use crate::simulation::{amount_to_price, base_token, spot_price};
+use crate::token::Token;
+use crate::v2;
+use crate::v3;
+use serde::Serialize;
+use std::io::{self, Write};
+
+// Price moves, as fractions of the spot price, reported by [depth_levels].
+pub const DEPTH_LEVELS: [f64; 3] = [0.001, 0.01, 0.05];
+
+// [Depth] is how much can be swapped into the pool before its marginal price moves by impact. base_in sells token_0 (V3) or token_x (V2) and pushes the price down; quote_in sells the other token and pushes it up.
+#[derive(PartialEq, Copy, Clone, Debug, Serialize)]
+pub struct Depth {
+    pub impact: f64,
+    pub base_in: f64,
+    pub quote_in: f64,
+}
+
+// [ImpactPoint] is one row of a price impact curve. Both prices are in the quote token per base token, so a curve for either direction reads the same way.
+#[derive(PartialEq, Copy, Clone, Debug, Serialize)]
+pub struct ImpactPoint {
+    pub amount_in: f64,
+    pub amount_out: f64,
+    pub effective_price: f64,
+    pub marginal_price: f64,
+}
+
+// [amount_to_move] is the input of token_in that moves the marginal price by impact, from [amount_to_price].
+pub fn amount_to_move(pool: &Pool, fee: f64, token_in: Token, impact: f64) -> f64 {
+    let sell_base = token_in == base_token(pool);
+    let target = if sell_base {
+        spot_price(pool) * (1. - impact)
+    } else {
+        spot_price(pool) * (1. + impact)
+    };
+    amount_to_price(pool, fee, target, sell_base)
+}
+
+pub fn depth_levels(pool: &Pool, fee: f64) -> Vec<Depth> {
+    let base = base_token(pool);
+    DEPTH_LEVELS
+        .iter()
+        .map(|impact| Depth {
+            impact: *impact,
+            base_in: amount_to_move(pool, fee, base, *impact),
+            quote_in: amount_to_move(pool, fee, base.other(), *impact),
+        })
+        .collect()
+}
+
+// [impact_curve] quotes points evenly spaced swaps of token_in up to max_amount, each against the untouched pool. An uninitialized V3 pool has no curve.
+pub fn impact_curve(
+    pool: &Pool,
+    fee: f64,
+    token_in: Token,
+    max_amount: f64,
+    points: usize,
+) -> Vec<ImpactPoint> {
+    let sell_base = token_in == base_token(pool);
+    (1..=points)
+        .filter_map(|i| {
+            let amount_in = max_amount * i as f64 / points as f64;
+            let (amount_out, marginal_price) = match pool {
+                Pool::V2(pool) => {
+                    let amount_out = v2::quote_amount_out(amount_in, pool, token_in, fee);
+                    let x = *pool.x.read().unwrap();
+                    let y = *pool.y.read().unwrap();
+                    let marginal_price = if sell_base {
+                        (y - amount_out) / (x + amount_in * (1. - fee))
+                    } else {
+                        (y + amount_in * (1. - fee)) / (x - amount_out)
+                    };
+                    (amount_out, marginal_price)
+                }
+                Pool::V3(pool) => {
+                    let after = pool.clone();
+                    let mut trader = v3::Trader {
+                        id: 1,
+                        amt_eth: Default::default(),
+                        amt_dai: Default::default(),
+                    };
+                    v3::v3_swap(&mut trader, &after, token_in, amount_in, fee).ok()?;
+                    let marginal_price = spot_price(&Pool::V3(after));
+                    (trader.balance(token_in.other()), marginal_price)
+                }
+            };
+            let effective_price = if sell_base {
+                amount_out / amount_in
+            } else {
+                amount_in / amount_out
+            };
+            Some(ImpactPoint {
+                amount_in,
+                amount_out,
+                effective_price,
+                marginal_price,
+            })
+        })
+        .collect()
+}
+
+pub fn write_impact_csv(curve: &[ImpactPoint], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "amount_in,amount_out,effective_price,marginal_price")?;
+    for point in curve {
+        writeln!(
+            out,
+            "{},{},{},{}",
+            point.amount_in, point.amount_out, point.effective_price, point.marginal_price
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::sync::RwLock;
+
+    fn v2_pool() -> Pool {
+        Pool::V2(Box::new(v2::Pool::new(
+            Token::Eth,
+            Token::Dai,
+            100.,
+            200000.,
+        )))
+    }
+
+    #[test]
+    fn v2_depth_moves_price_by_each_level() {
+        let pool = v2_pool();
+
+        let levels = depth_levels(&pool, 0.003);
+
+        assert_eq!(levels.len(), 3);
+        for level in &levels {
+            let sold = impact_curve(&pool, 0.003, Token::Eth, level.base_in, 1)[0];
+            let bought = impact_curve(&pool, 0.003, Token::Dai, level.quote_in, 1)[0];
+            assert!((sold.marginal_price / 2000. - (1. - level.impact)).abs() < 1e-4);
+            assert!((bought.marginal_price / 2000. - (1. + level.impact)).abs() < 1e-4);
+        }
+        assert!(levels[0].base_in < levels[1].base_in && levels[1].base_in < levels[2].base_in);
+    }
+
+    #[test]
+    fn v3_depth_moves_price_by_each_level() {
+        let mut pool = v3::uniswap_v3_pool::new(Token::Eth, Token::Dai, v3::price_to_sqrtp(5000.));
+        let owner = v3::Trader {
+            id: 2,
+            amt_eth: RwLock::new(1e30),
+            amt_dai: RwLock::new(1e30),
+        };
+        pool.mint(&owner, -86000, 86000, 1e18).unwrap();
+        // The 5% level crosses both ends of this range.
+        pool.mint(&owner, 8480, 8560, 1e18).unwrap();
+        let pool = Pool::V3(Box::new(pool));
+        let spot = spot_price(&pool);
+
+        let levels = depth_levels(&pool, 0.03);
+
+        assert!(levels.iter().all(|l| l.base_in > 0. && l.quote_in > 0.));
+        assert!(levels[0].quote_in < levels[2].quote_in);
+        for level in &levels {
+            let sold = impact_curve(&pool, 0.03, Token::Eth, level.base_in, 1)[0];
+            let bought = impact_curve(&pool, 0.03, Token::Dai, level.quote_in, 1)[0];
+            assert!((sold.marginal_price / spot - (1. - level.impact)).abs() < 1e-9);
+            assert!((bought.marginal_price / spot - (1. + level.impact)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn curve_exports_csv() {
+        let curve = impact_curve(&v2_pool(), 0., Token::Eth, 10., 2);
+        let mut out = vec![];
+
+        write_impact_csv(&curve, &mut out).unwrap();
+
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "amount_in,amount_out,effective_price,marginal_price"
+        );
+        assert_eq!(lines.len(), 3);
+        assert!(curve[0].effective_price > curve[1].effective_price);
+        assert!(curve[1].effective_price > curve[1].marginal_price);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::v3::uniswap_v3_pool;
+use serde::Serialize;
+use std::io::{self, Write};
+
+// [LiquidityRange] is the liquidity active while the pool's tick is in [lower_tick, upper_tick).
+#[derive(PartialEq, Copy, Clone, Debug, Serialize)]
+pub struct LiquidityRange {
+    pub lower_tick: i32,
+    pub upper_tick: i32,
+    pub liquidity: f64,
+}
+
+// [liquidity_distribution] sums liquidity_net across every initialized tick, lowest first, and returns one range per pair of neighbouring ticks. Below the lowest tick and above the highest there is no liquidity, so those ranges are left out.
+pub fn liquidity_distribution(pool: &uniswap_v3_pool) -> Vec<LiquidityRange> {
+    let tick_mapping = pool.tick_mapping.read().unwrap();
+    let mut ticks: Vec<(i32, f64)> = tick_mapping
+        .iter()
+        .filter(|(_, t)| *t.liquidity.read().unwrap() != 0.)
+        .map(|(tick, t)| (*tick, *t.liquidity_net.read().unwrap()))
+        .collect();
+    ticks.sort_by_key(|(tick, _)| *tick);
+
+    let mut liquidity = 0.;
+    ticks
+        .windows(2)
+        .map(|pair| {
+            liquidity += pair[0].1;
+            LiquidityRange {
+                lower_tick: pair[0].0,
+                upper_tick: pair[1].0,
+                liquidity,
+            }
+        })
+        .collect()
+}
+
+pub fn write_distribution_csv(ranges: &[LiquidityRange], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "lower_tick,upper_tick,liquidity")?;
+    for range in ranges {
+        writeln!(
+            out,
+            "{},{},{}",
+            range.lower_tick, range.upper_tick, range.liquidity
+        )?;
+    }
+    Ok(())
+}
+
+// [render_histogram] draws one bar per range that overlaps window ticks either side of current_tick, scaled so the deepest range shown is width characters wide. The range holding current_tick is marked.
+pub fn render_histogram(
+    ranges: &[LiquidityRange],
+    current_tick: i32,
+    window: i32,
+    width: usize,
+) -> String {
+    let shown: Vec<&LiquidityRange> = ranges
+        .iter()
+        .filter(|r| r.upper_tick > current_tick - window && r.lower_tick <= current_tick + window)
+        .collect();
+    let deepest = shown.iter().map(|r| r.liquidity).fold(0., f64::max);
+    let label_width = shown
+        .iter()
+        .map(|r| format!("{}..{}", r.lower_tick, r.upper_tick).len())
+        .max()
+        .unwrap_or(0);
+
+    let mut histogram = String::new();
+    for range in shown {
+        let bar = if deepest > 0. {
+            (range.liquidity / deepest * width as f64).round() as usize
+        } else {
+            0
+        };
+        let marker = if range.lower_tick <= current_tick && current_tick < range.upper_tick {
+            " <- tick"
+        } else {
+            ""
+        };
+        histogram.push_str(&format!(
+            "{:>label_width$} |{:<width$}| {}{}\n",
+            format!("{}..{}", range.lower_tick, range.upper_tick),
+            "#".repeat(bar),
+            range.liquidity,
+            marker,
+        ));
+    }
+    histogram
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::token::Token;
+    use crate::v3::{tick_to_price, v3_swap, Trader};
+    use std::sync::RwLock;
+
+    fn pool() -> uniswap_v3_pool {
+        let mut pool =
+            uniswap_v3_pool::new(Token::Eth, Token::Dai, 5602277097478614198912276234240.);
+        let owner = Trader {
+            id: 2,
+            amt_eth: RwLock::new(0.),
+            amt_dai: RwLock::new(0.),
+        };
+        pool.mint(&owner, 8400, 8800, 100.).unwrap();
+        pool.mint(&owner, 8500, 8600, 50.).unwrap();
+        pool.mint(&owner, 8700, 9000, 25.).unwrap();
+        pool
+    }
+
+    #[test]
+    fn sums_overlapping_positions() {
+        let ranges = liquidity_distribution(&pool());
+
+        let flat: Vec<(i32, i32, f64)> = ranges
+            .iter()
+            .map(|r| (r.lower_tick, r.upper_tick, r.liquidity))
+            .collect();
+        assert_eq!(
+            flat,
+            vec![
+                (8400, 8500, 100.),
+                (8500, 8600, 150.),
+                (8600, 8700, 100.),
+                (8700, 8800, 125.),
+                (8800, 9000, 25.),
+            ]
+        );
+    }
+
+    #[test]
+    fn burned_position_leaves_no_liquidity() {
+        let mut pool = pool();
+        let owner = Trader {
+            id: 2,
+            amt_eth: RwLock::new(0.),
+            amt_dai: RwLock::new(0.),
+        };
+        pool.mint(&owner, 8500, 8600, -50.).unwrap();
+
+        let ranges = liquidity_distribution(&pool);
+
+        assert!(ranges.iter().all(|r| r.liquidity != 150.));
+        assert_eq!(ranges[0].upper_tick, 8700);
+    }
+
+    #[test]
+    fn histogram_marks_current_range() {
+        let ranges = liquidity_distribution(&pool());
+        let mut csv = vec![];
+        write_distribution_csv(&ranges, &mut csv).unwrap();
+
+        let histogram = render_histogram(&ranges, 8521, 100, 30);
+
+        assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 6);
+        let lines: Vec<&str> = histogram.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("8500..8600 |"));
+        assert!(lines[1].contains(&"#".repeat(30)));
+        assert!(lines[1].ends_with("<- tick"));
+        assert!(!lines[0].ends_with("<- tick"));
+    }
+
+    #[test]
+    fn swap_sees_the_distributed_liquidity() {
+        let ranges = liquidity_distribution(&pool());
+
+        for target in [8750, 8450] {
+            let pool = pool();
+            let swap = pool.swap_to_price(tick_to_price(target), 0.).unwrap();
+            let mut trader = Trader {
+                id: 1,
+                amt_eth: RwLock::new(0.),
+                amt_dai: RwLock::new(0.),
+            };
+            v3_swap(&mut trader, &pool, swap.token_in, swap.amount_in, 0.).unwrap();
+
+            let tick = *pool.tick.read().unwrap();
+            let range = ranges
+                .iter()
+                .find(|r| r.lower_tick <= tick && tick < r.upper_tick)
+                .unwrap();
+            assert_eq!(*pool.liquidity.read().unwrap(), range.liquidity);
+        }
+    }
+}
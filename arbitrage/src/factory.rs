@@ -0,0 +1,411 @@
+use crate::opportunity::Venue;
+use crate::scenario::Pool;
+use crate::token::{Token, TokenRegistry};
+use crate::v2;
+use crate::v3::{uniswap_v3_pool, PoolError};
+use sha3::{Digest, Keccak256};
+use std::collections::HashMap;
+use std::fmt;
+
+pub type Address = [u8; 20];
+
+// Mainnet deployments, used by [Factory::default].
+pub const V2_FACTORY: &str = "0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f";
+pub const V2_INIT_CODE_HASH: &str =
+    "0x96e8ac4277198ff8b6f785478aa9a39f403cb768dd02cbee326c3e7da348845f";
+pub const V3_FACTORY: &str = "0x1F98431c8aD98523631AE4a59f267346ea31F984";
+pub const V3_INIT_CODE_HASH: &str =
+    "0xe34f199b19b2b4f47f68442619d555527d244f78a3297ea89325f843f87b8b54";
+
+// Fee of every V2 pair, in hundredths of a basis point like V3 fee tiers.
+pub const V2_FEE_PIPS: u32 = 3000;
+
+// [parse_hex] reads a 0x-prefixed or bare hex string into exactly N bytes.
+pub fn parse_hex<const N: usize>(s: &str) -> Result<[u8; N], FactoryError> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    if digits.len() != 2 * N || !digits.is_ascii() {
+        return Err(FactoryError::BadHex(s.to_string()));
+    }
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&digits[2 * i..2 * i + 2], 16)
+            .map_err(|_| FactoryError::BadHex(s.to_string()))?;
+    }
+    Ok(bytes)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    let digits: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", digits)
+}
+
+pub fn fee_to_pips(fee: f64) -> u32 {
+    (fee * 1e6).round() as u32
+}
+
+// [create2_address] is keccak256(0xff ++ deployer ++ salt ++ init_code_hash)[12..], the address CREATE2 deploys to.
+pub fn create2_address(deployer: &Address, salt: &[u8; 32], init_code_hash: &[u8; 32]) -> Address {
+    let mut hasher = Keccak256::new();
+    hasher.update([0xff]);
+    hasher.update(deployer);
+    hasher.update(salt);
+    hasher.update(init_code_hash);
+    let mut address = [0; 20];
+    address.copy_from_slice(&hasher.finalize()[12..]);
+    address
+}
+
+// [FactoryConfig] is a deployer address and the init code hash of the contracts it creates.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct FactoryConfig {
+    pub address: Address,
+    pub init_code_hash: [u8; 32],
+}
+
+impl FactoryConfig {
+    pub fn new(address: &str, init_code_hash: &str) -> Result<Self, FactoryError> {
+        Ok(FactoryConfig {
+            address: parse_hex(address)?,
+            init_code_hash: parse_hex(init_code_hash)?,
+        })
+    }
+}
+
+// [PoolKey] identifies a pool. token_0 sorts before token_1 by address, as in both factories.
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub struct PoolKey {
+    pub venue: Venue,
+    pub token_0: Token,
+    pub token_1: Token,
+    pub fee_pips: u32,
+}
+
+pub struct RegisteredPool {
+    pub key: PoolKey,
+    pub address: Address,
+    pub pool: Pool,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum FactoryError {
+    BadHex(String),
+    IdenticalTokens(Token),
+    PoolExists(Address),
+    Pool(PoolError),
+}
+
+impl fmt::Display for FactoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FactoryError::BadHex(s) => write!(f, "{:?} is not a hex value of the right length", s),
+            FactoryError::IdenticalTokens(token) => {
+                write!(f, "cannot pair {:?} with itself", token)
+            }
+            FactoryError::PoolExists(address) => {
+                write!(f, "pool {} already exists", to_hex(address))
+            }
+            FactoryError::Pool(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for FactoryError {}
+
+impl From<PoolError> for FactoryError {
+    fn from(e: PoolError) -> Self {
+        FactoryError::Pool(e)
+    }
+}
+
+// [Factory] creates V2 pairs and V3 pools at their CREATE2 addresses and indexes them by key, by token pair and by address. Token addresses come from the token registry, falling back to the mainnet token.
+pub struct Factory {
+    pub v2: FactoryConfig,
+    pub v3: FactoryConfig,
+    tokens: TokenRegistry,
+    pools: Vec<RegisteredPool>,
+    by_key: HashMap<PoolKey, usize>,
+    by_pair: HashMap<(Token, Token), Vec<usize>>,
+    by_address: HashMap<Address, usize>,
+}
+
+impl Default for Factory {
+    fn default() -> Self {
+        Factory::new(
+            FactoryConfig::new(V2_FACTORY, V2_INIT_CODE_HASH).unwrap(),
+            FactoryConfig::new(V3_FACTORY, V3_INIT_CODE_HASH).unwrap(),
+            TokenRegistry::default(),
+        )
+    }
+}
+
+impl Factory {
+    pub fn new(v2: FactoryConfig, v3: FactoryConfig, tokens: TokenRegistry) -> Self {
+        Factory {
+            v2,
+            v3,
+            tokens,
+            pools: vec![],
+            by_key: HashMap::new(),
+            by_pair: HashMap::new(),
+            by_address: HashMap::new(),
+        }
+    }
+
+    pub fn token_address(&self, token: Token) -> Address {
+        self.tokens
+            .address(token)
+            .and_then(|address| parse_hex(&address).ok())
+            .unwrap_or_else(|| parse_hex(token.mainnet_address()).unwrap())
+    }
+
+    // [sort_tokens] orders a pair by address, like UniswapV2Library.sortTokens.
+    pub fn sort_tokens(
+        &self,
+        token_a: Token,
+        token_b: Token,
+    ) -> Result<(Token, Token), FactoryError> {
+        if token_a == token_b {
+            return Err(FactoryError::IdenticalTokens(token_a));
+        }
+        if self.token_address(token_a) < self.token_address(token_b) {
+            Ok((token_a, token_b))
+        } else {
+            Ok((token_b, token_a))
+        }
+    }
+
+    // [pool_address] is where the factory for key.venue deploys the pool. V2 salts with keccak256(abi.encodePacked(token0, token1)); V3 adds the fee and pads every value to a word, as abi.encode does.
+    pub fn pool_address(&self, key: &PoolKey) -> Address {
+        let token_0 = self.token_address(key.token_0);
+        let token_1 = self.token_address(key.token_1);
+        let mut hasher = Keccak256::new();
+        let config = match key.venue {
+            Venue::V2 => {
+                hasher.update(token_0);
+                hasher.update(token_1);
+                &self.v2
+            }
+            Venue::V3 => {
+                hasher.update([0; 12]);
+                hasher.update(token_0);
+                hasher.update([0; 12]);
+                hasher.update(token_1);
+                let mut fee = [0; 32];
+                fee[28..].copy_from_slice(&key.fee_pips.to_be_bytes());
+                hasher.update(fee);
+                &self.v3
+            }
+        };
+        let salt: [u8; 32] = hasher.finalize().into();
+        create2_address(&config.address, &salt, &config.init_code_hash)
+    }
+
+    fn register(&mut self, key: PoolKey, pool: Pool) -> Result<&RegisteredPool, FactoryError> {
+        let address = self.pool_address(&key);
+        if self.by_key.contains_key(&key) {
+            return Err(FactoryError::PoolExists(address));
+        }
+        let index = self.pools.len();
+        self.pools.push(RegisteredPool { key, address, pool });
+        self.by_key.insert(key, index);
+        self.by_pair
+            .entry((key.token_0, key.token_1))
+            .or_default()
+            .push(index);
+        self.by_address.insert(address, index);
+        Ok(&self.pools[index])
+    }
+
+    // [create_v2_pair] deploys a pair holding reserve_a of token_a and reserve_b of token_b.
+    pub fn create_v2_pair(
+        &mut self,
+        token_a: Token,
+        token_b: Token,
+        reserve_a: f64,
+        reserve_b: f64,
+    ) -> Result<&RegisteredPool, FactoryError> {
+        let (token_0, token_1) = self.sort_tokens(token_a, token_b)?;
+        let (reserve_0, reserve_1) = if token_0 == token_a {
+            (reserve_a, reserve_b)
+        } else {
+            (reserve_b, reserve_a)
+        };
+        let key = PoolKey {
+            venue: Venue::V2,
+            token_0,
+            token_1,
+            fee_pips: V2_FEE_PIPS,
+        };
+        let pool = v2::Pool::new(token_0, token_1, reserve_0, reserve_1);
+        self.register(key, Pool::V2(Box::new(pool)))
+    }
+
+    // [create_v3_pool] deploys an uninitialized pool for one of the fee tiers; call initialize on it before use.
+    pub fn create_v3_pool(
+        &mut self,
+        token_a: Token,
+        token_b: Token,
+        fee: f64,
+    ) -> Result<&RegisteredPool, FactoryError> {
+        let (token_0, token_1) = self.sort_tokens(token_a, token_b)?;
+        let pool = uniswap_v3_pool::create(token_0, token_1, fee)?;
+        let key = PoolKey {
+            venue: Venue::V3,
+            token_0,
+            token_1,
+            fee_pips: fee_to_pips(fee),
+        };
+        self.register(key, Pool::V3(Box::new(pool)))
+    }
+
+    pub fn get(&self, key: &PoolKey) -> Option<&RegisteredPool> {
+        self.by_key.get(key).map(|index| &self.pools[*index])
+    }
+
+    pub fn get_v2_pair(&self, token_a: Token, token_b: Token) -> Option<&RegisteredPool> {
+        let (token_0, token_1) = self.sort_tokens(token_a, token_b).ok()?;
+        self.get(&PoolKey {
+            venue: Venue::V2,
+            token_0,
+            token_1,
+            fee_pips: V2_FEE_PIPS,
+        })
+    }
+
+    pub fn get_v3_pool(&self, token_a: Token, token_b: Token, fee: f64) -> Option<&RegisteredPool> {
+        let (token_0, token_1) = self.sort_tokens(token_a, token_b).ok()?;
+        self.get(&PoolKey {
+            venue: Venue::V3,
+            token_0,
+            token_1,
+            fee_pips: fee_to_pips(fee),
+        })
+    }
+
+    pub fn get_by_address(&self, address: &Address) -> Option<&RegisteredPool> {
+        self.by_address
+            .get(address)
+            .map(|index| &self.pools[*index])
+    }
+
+    // [pools_for_pair] is every pool trading the pair on either venue, in the order they were created.
+    pub fn pools_for_pair(&self, token_a: Token, token_b: Token) -> Vec<&RegisteredPool> {
+        let Ok(pair) = self.sort_tokens(token_a, token_b) else {
+            return vec![];
+        };
+        self.by_pair
+            .get(&pair)
+            .map(|indexes| indexes.iter().map(|index| &self.pools[*index]).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn pools(&self) -> &[RegisteredPool] {
+        &self.pools
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn computes_mainnet_addresses() {
+        let factory = Factory::default();
+        let v2_key = PoolKey {
+            venue: Venue::V2,
+            token_0: Token::Dai,
+            token_1: Token::Eth,
+            fee_pips: V2_FEE_PIPS,
+        };
+        let v3_key = PoolKey {
+            venue: Venue::V3,
+            fee_pips: 3000,
+            ..v2_key
+        };
+
+        assert_eq!(
+            factory.sort_tokens(Token::Eth, Token::Dai),
+            Ok((Token::Dai, Token::Eth))
+        );
+        assert_eq!(
+            to_hex(&factory.pool_address(&v2_key)),
+            "0xa478c2975ab1ea89e8196811f51a7b7ade33eb11"
+        );
+        assert_eq!(
+            to_hex(&factory.pool_address(&v3_key)),
+            "0xc2e9f25be6257c210d7adf0d4cd6e3e881ba25f8"
+        );
+    }
+
+    #[test]
+    fn indexes_pools_by_pair_fee_and_address() {
+        let mut factory = Factory::default();
+        let pair = factory
+            .create_v2_pair(Token::Eth, Token::Dai, 4., 8000.)
+            .unwrap()
+            .address;
+        factory
+            .create_v3_pool(Token::Dai, Token::Eth, 0.003)
+            .unwrap();
+        factory
+            .create_v3_pool(Token::Eth, Token::Dai, 0.0005)
+            .unwrap();
+
+        assert_eq!(
+            factory.create_v3_pool(Token::Eth, Token::Dai, 0.003).err(),
+            Some(FactoryError::PoolExists(
+                factory
+                    .get_v3_pool(Token::Eth, Token::Dai, 0.003)
+                    .unwrap()
+                    .address
+            ))
+        );
+        assert!(matches!(
+            factory.create_v3_pool(Token::Eth, Token::Dai, 0.002),
+            Err(FactoryError::Pool(PoolError::UnsupportedFee(_)))
+        ));
+        assert_eq!(
+            factory.create_v2_pair(Token::Eth, Token::Eth, 1., 1.).err(),
+            Some(FactoryError::IdenticalTokens(Token::Eth))
+        );
+
+        let pools = factory.pools_for_pair(Token::Eth, Token::Dai);
+        let fees: Vec<u32> = pools.iter().map(|p| p.key.fee_pips).collect();
+        assert_eq!(fees, vec![3000, 3000, 500]);
+        let Pool::V2(v2_pool) = &factory.get_by_address(&pair).unwrap().pool else {
+            panic!("expected the V2 pair");
+        };
+        assert_eq!(v2_pool.token_x, Token::Dai);
+        assert_eq!(*v2_pool.x.read().unwrap(), 8000.);
+        let Pool::V3(v3_pool) = &factory
+            .get_v3_pool(Token::Dai, Token::Eth, 0.0005)
+            .unwrap()
+            .pool
+        else {
+            panic!("expected a V3 pool");
+        };
+        assert_eq!(v3_pool.tick_spacing, 10);
+        assert!(!v3_pool.is_initialized());
+    }
+
+    #[test]
+    fn init_code_hash_is_configurable() {
+        let v2 = FactoryConfig::new(V2_FACTORY, &to_hex(&[1; 32])).unwrap();
+        let v3 = FactoryConfig::new(V3_FACTORY, V3_INIT_CODE_HASH).unwrap();
+        let factory = Factory::new(v2, v3, TokenRegistry::default());
+
+        let pair = factory.pool_address(&PoolKey {
+            venue: Venue::V2,
+            token_0: Token::Dai,
+            token_1: Token::Eth,
+            fee_pips: V2_FEE_PIPS,
+        });
+
+        assert_ne!(to_hex(&pair), "0xa478c2975ab1ea89e8196811f51a7b7ade33eb11");
+        assert_eq!(
+            FactoryConfig::new("0x12", V2_INIT_CODE_HASH),
+            Err(FactoryError::BadHex("0x12".to_string()))
+        );
+    }
+}
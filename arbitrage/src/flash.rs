@@ -0,0 +1,58 @@
+use crate::token::Token;
+use std::fmt;
+
+// [FlashError] is why a flash swap or flash loan reverted. The pool's state is left as it was before the call.
+#[derive(PartialEq, Debug)]
+pub enum FlashError {
+    InvalidAmount {
+        token: Token,
+        amount: f64,
+    },
+    InsufficientLiquidity {
+        token: Token,
+        requested: f64,
+        available: f64,
+    },
+    Underpaid {
+        token: Token,
+        owed: f64,
+        repaid: f64,
+    },
+    InvariantBroken {
+        before: f64,
+        after: f64,
+    },
+    Reentrant,
+}
+
+impl fmt::Display for FlashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FlashError::InvalidAmount { token, amount } => {
+                write!(f, "cannot lend {} {:?}", amount, token)
+            }
+            FlashError::InsufficientLiquidity {
+                token,
+                requested,
+                available,
+            } => write!(
+                f,
+                "cannot lend {} {:?}, the pool holds {}",
+                requested, token, available
+            ),
+            FlashError::Underpaid {
+                token,
+                owed,
+                repaid,
+            } => write!(f, "owed {} {:?} but repaid {}", owed, token, repaid),
+            FlashError::InvariantBroken { before, after } => write!(
+                f,
+                "reserves product fell from {} to {} after fees",
+                before, after
+            ),
+            FlashError::Reentrant => write!(f, "cannot swap in the pool that is lending"),
+        }
+    }
+}
+
+impl std::error::Error for FlashError {}
@@ -0,0 +1,95 @@
+use crate::token::Token;
+use crate::v2;
+
+// Gas amounts are in gas units, fees in gwei. The defaults are rough mainnet figures.
+#[derive(Copy, Clone, Debug)]
+pub struct GasModel {
+    pub tx_base_gas: f64,
+    pub hop_overhead_gas: f64,
+    pub v2_pool_gas: f64,
+    pub v3_pool_gas: f64,
+    pub v3_tick_cross_gas: f64,
+    pub base_fee_gwei: f64,
+    pub priority_fee_gwei: f64,
+}
+
+impl Default for GasModel {
+    fn default() -> Self {
+        GasModel {
+            tx_base_gas: 21000.,
+            hop_overhead_gas: 15000.,
+            v2_pool_gas: 45000.,
+            v3_pool_gas: 80000.,
+            v3_tick_cross_gas: 20000.,
+            base_fee_gwei: 20.,
+            priority_fee_gwei: 1.,
+        }
+    }
+}
+
+impl GasModel {
+    pub fn v2_hop_gas(&self) -> f64 {
+        self.hop_overhead_gas + self.v2_pool_gas
+    }
+
+    pub fn v3_hop_gas(&self, ticks_crossed: i32) -> f64 {
+        self.hop_overhead_gas + self.v3_pool_gas + self.v3_tick_cross_gas * ticks_crossed as f64
+    }
+
+    pub fn tx_gas(&self, hops_gas: f64) -> f64 {
+        self.tx_base_gas + hops_gas
+    }
+
+    pub fn gas_price_eth(&self) -> f64 {
+        (self.base_fee_gwei + self.priority_fee_gwei) * 1e-9
+    }
+
+    // [cost_in_token] prices gas_units in ETH and converts to token at the reference pool's spot price.
+    pub fn cost_in_token(&self, gas_units: f64, token: Token, reference: &v2::Pool) -> f64 {
+        let cost_eth = gas_units * self.gas_price_eth();
+        if token == Token::Eth {
+            return cost_eth;
+        }
+        let x = *reference.x.read().unwrap();
+        let y = *reference.y.read().unwrap();
+        if reference.token_x == Token::Eth {
+            cost_eth * y / x
+        } else {
+            cost_eth * x / y
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn reference_pool() -> v2::Pool {
+        v2::Pool::new(Token::Eth, Token::Dai, 10., 20000.)
+    }
+
+    #[test]
+    fn v3_hop_charges_per_tick() {
+        let gas = GasModel::default();
+
+        assert_eq!(gas.v3_hop_gas(0), 95000.);
+        assert_eq!(gas.v3_hop_gas(2), 135000.);
+    }
+
+    #[test]
+    fn converts_through_reference_pool() {
+        let gas = GasModel {
+            base_fee_gwei: 9.,
+            priority_fee_gwei: 1.,
+            ..GasModel::default()
+        };
+        let reference = reference_pool();
+
+        let eth = gas.cost_in_token(100000., Token::Eth, &reference);
+        let dai = gas.cost_in_token(100000., Token::Dai, &reference);
+
+        assert!((eth - 0.001).abs() < 1e-12);
+        assert!((dai - 2.).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,24 @@
+pub mod backtest;
+pub mod bus;
+pub mod cex;
+pub mod cli;
+pub mod cross;
+pub mod depth;
+pub mod distribution;
+pub mod factory;
+pub mod flash;
+pub mod gas;
+pub mod logs;
+pub mod math;
+pub mod opportunity;
+pub mod oracle;
+pub mod price_path;
+pub mod scenario;
+pub mod simulation;
+pub mod snapshot;
+pub mod state;
+pub mod token;
+pub mod v2;
+pub mod v2_logs;
+pub mod v3;
+pub mod v3_logs;
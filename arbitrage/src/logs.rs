@@ -0,0 +1,239 @@
+use crate::v3::PoolError;
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum LogError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Hex(String),
+    MissingTopic(usize),
+    ShortData { expected: usize, found: usize },
+    Pool(PoolError),
+}
+
+impl fmt::Display for LogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogError::Io(e) => write!(f, "could not read log file: {}", e),
+            LogError::Json(e) => write!(f, "malformed log entry: {}", e),
+            LogError::Hex(s) => write!(f, "invalid hex {:?}", s),
+            LogError::MissingTopic(i) => write!(f, "log has no topic {}", i),
+            LogError::ShortData { expected, found } => {
+                write!(f, "log data has {} words, expected {}", found, expected)
+            }
+            LogError::Pool(e) => write!(f, "log cannot be applied: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LogError {}
+
+impl From<PoolError> for LogError {
+    fn from(e: PoolError) -> Self {
+        LogError::Pool(e)
+    }
+}
+
+impl From<std::io::Error> for LogError {
+    fn from(e: std::io::Error) -> Self {
+        LogError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for LogError {
+    fn from(e: serde_json::Error) -> Self {
+        LogError::Json(e)
+    }
+}
+
+// One entry as returned by eth_getLogs. Block number and log index may be hex quantities or plain numbers.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RawLog {
+    pub address: String,
+    pub topics: Vec<String>,
+    pub data: String,
+    #[serde(deserialize_with = "quantity")]
+    pub block_number: u64,
+    #[serde(deserialize_with = "quantity")]
+    pub log_index: u64,
+    #[serde(default)]
+    pub removed: bool,
+}
+
+fn quantity<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Quantity {
+        Number(u64),
+        Hex(String),
+    }
+    match Quantity::deserialize(deserializer)? {
+        Quantity::Number(n) => Ok(n),
+        Quantity::Hex(s) => u64::from_str_radix(s.trim_start_matches("0x"), 16)
+            .map_err(|_| serde::de::Error::custom(format!("invalid quantity {:?}", s))),
+    }
+}
+
+impl RawLog {
+    pub fn topic(&self, index: usize) -> Result<Word, LogError> {
+        let topic = self.topics.get(index).ok_or(LogError::MissingTopic(index))?;
+        let words = decode_words(topic)?;
+        words
+            .into_iter()
+            .next()
+            .ok_or(LogError::Hex(topic.clone()))
+    }
+
+    // [words] splits data into 32-byte words and fails unless there are at least expected of them.
+    pub fn words(&self, expected: usize) -> Result<Vec<Word>, LogError> {
+        let words = decode_words(&self.data)?;
+        if words.len() < expected {
+            return Err(LogError::ShortData {
+                expected,
+                found: words.len(),
+            });
+        }
+        Ok(words)
+    }
+
+    pub fn is_from(&self, address: &str) -> bool {
+        self.address.eq_ignore_ascii_case(address)
+    }
+}
+
+// [read_jsonl] reads one RawLog per non-empty line and returns them sorted by block number and log index, with removed logs dropped.
+pub fn read_jsonl<P: AsRef<Path>>(path: P) -> Result<Vec<RawLog>, LogError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut logs = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let log: RawLog = serde_json::from_str(&line)?;
+        if !log.removed {
+            logs.push(log);
+        }
+    }
+    logs.sort_by_key(|log| (log.block_number, log.log_index));
+    Ok(logs)
+}
+
+// [group_by_block] splits logs that are already in order into runs that share a block number.
+pub fn group_by_block(logs: &[RawLog]) -> Vec<(u64, &[RawLog])> {
+    let mut blocks = vec![];
+    let mut start = 0;
+    for i in 1..=logs.len() {
+        if i == logs.len() || logs[i].block_number != logs[start].block_number {
+            blocks.push((logs[start].block_number, &logs[start..i]));
+            start = i;
+        }
+    }
+    blocks
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct Word(pub [u8; 32]);
+
+impl Word {
+    pub fn as_u256(&self) -> f64 {
+        self.0.iter().fold(0., |acc, &b| acc * 256. + b as f64)
+    }
+
+    // [as_i256] reads the word as two's complement.
+    pub fn as_i256(&self) -> f64 {
+        if self.0[0] & 0x80 == 0 {
+            return self.as_u256();
+        }
+        let mut magnitude = [0u8; 32];
+        let mut carry = true;
+        for i in (0..32).rev() {
+            let (b, c) = (!self.0[i]).overflowing_add(carry as u8);
+            magnitude[i] = b;
+            carry = c;
+        }
+        -Word(magnitude).as_u256()
+    }
+
+    // [as_i24] reads a sign-extended int24 such as a tick.
+    pub fn as_i24(&self) -> i32 {
+        i32::from_be_bytes([self.0[28], self.0[29], self.0[30], self.0[31]])
+    }
+
+    pub fn as_address(&self) -> String {
+        let hex: String = self.0[12..].iter().map(|b| format!("{:02x}", b)).collect();
+        format!("0x{}", hex)
+    }
+}
+
+pub fn decode_words(hex: &str) -> Result<Vec<Word>, LogError> {
+    let digits = hex.trim_start_matches("0x");
+    if !digits.len().is_multiple_of(64) || !digits.is_ascii() {
+        return Err(LogError::Hex(hex.to_string()));
+    }
+    let mut words = vec![];
+    for chunk in digits.as_bytes().chunks(64) {
+        let mut word = [0u8; 32];
+        for (i, pair) in chunk.chunks(2).enumerate() {
+            let s = std::str::from_utf8(pair).unwrap();
+            word[i] = u8::from_str_radix(s, 16).map_err(|_| LogError::Hex(hex.to_string()))?;
+        }
+        words.push(Word(word));
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn decodes_signed_and_unsigned_words() {
+        let words = decode_words(&format!(
+            "0x{}{}{}",
+            "00000000000000000000000000000000000000000000000000000000000003e8",
+            "fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc18",
+            "fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffeb2dc",
+        ))
+        .unwrap();
+
+        assert_eq!(words[0].as_u256(), 1000.);
+        assert_eq!(words[1].as_i256(), -1000.);
+        assert_eq!(words[2].as_i24(), -85284);
+    }
+
+    #[test]
+    fn parses_rpc_quantities() {
+        let log: RawLog = serde_json::from_str(
+            r#"{"address":"0xAB","topics":[],"data":"0x","blockNumber":"0x10","logIndex":3}"#,
+        )
+        .unwrap();
+
+        assert_eq!(log.block_number, 16);
+        assert_eq!(log.log_index, 3);
+        assert!(log.is_from("0xab"));
+        assert!(!log.removed);
+    }
+
+    #[test]
+    fn rejects_short_data() {
+        let log: RawLog = serde_json::from_str(
+            r#"{"address":"0xab","topics":[],"data":"0x","blockNumber":1,"logIndex":0}"#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            log.words(2),
+            Err(LogError::ShortData {
+                expected: 2,
+                found: 0
+            })
+        ));
+    }
+}
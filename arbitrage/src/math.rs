@@ -1,13 +1,26 @@
-const base: f64 = 2.;
+const BASE: f64 = 2.;
 
-const min_tick: i32 = -887272;
+const MIN_TICK: i32 = -887272;
+
+// Uniswap V3's TickMath bounds on sqrt_price_x96: MIN_SQRT_RATIO is allowed, MAX_SQRT_RATIO is not.
+const MIN_SQRT_RATIO: f64 = 4295128739.;
+const MAX_SQRT_RATIO: f64 = 1461446703485210103287273052203988822378723970342.;
 
 pub fn get_min_tick() -> i32 {
-  min_tick
+  MIN_TICK
 }
 pub fn get_max_tick() -> i32 {
-  -min_tick
+  -MIN_TICK
 }
 pub fn get_q96() -> f64 {
-  base.powf(96.)
+  BASE.powf(96.)
+}
+pub fn get_q112() -> f64 {
+  BASE.powf(112.)
+}
+pub fn get_min_sqrt_ratio() -> f64 {
+  MIN_SQRT_RATIO
+}
+pub fn get_max_sqrt_ratio() -> f64 {
+  MAX_SQRT_RATIO
 }
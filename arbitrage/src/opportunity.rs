@@ -0,0 +1,286 @@
+use crate::scenario::Pool;
+use crate::token::{Token, TokenRegistry};
+use crate::{v2, v3};
+use serde::Serialize;
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Serialize)]
+pub enum Venue {
+    V2,
+    V3,
+}
+
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct Leg {
+    pub venue: Venue,
+    pub token_in: Token,
+    pub amount_in: f64,
+    pub expected_out: f64,
+    pub min_out: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Opportunity {
+    pub token_in: Token,
+    pub amount_in: f64,
+    pub gross_profit: f64,
+    pub gas_cost: f64,
+    pub net_profit: f64,
+    pub legs: Vec<Leg>,
+}
+
+impl Opportunity {
+    pub fn roi(&self) -> f64 {
+        if self.amount_in == 0. {
+            return 0.;
+        }
+        self.net_profit / self.amount_in
+    }
+}
+
+// [Settlement] is an opportunity requoted with the actual amounts each transfer delivers. unsafe_legs are the legs whose delivered output falls below their min_out, so they would revert on chain.
+#[derive(Clone, Debug, Serialize)]
+pub struct Settlement {
+    pub opportunity: Opportunity,
+    pub unsafe_legs: Vec<usize>,
+}
+
+impl Settlement {
+    pub fn is_safe(&self) -> bool {
+        self.unsafe_legs.is_empty()
+    }
+}
+
+impl Opportunity {
+    // [settle] re-quotes the legs with the amounts each transfer actually delivers. pools[i] is the pool and swap fee of leg i. What reaches the pool is what token_in delivers after its transfer tax, the pool quotes its output for that amount, and what reaches the trader is that output after the output token's tax. A rebase scales the trader's balance once, so it is applied to the proceeds of the last leg rather than on every transfer. The legs keep their quoted min_out. It panics unless there is one pool per leg.
+    pub fn settle(&self, pools: &[(&Pool, f64)], tokens: &TokenRegistry) -> Settlement {
+        assert_eq!(
+            pools.len(),
+            self.legs.len(),
+            "settle needs one pool per leg"
+        );
+        let exact = self
+            .legs
+            .iter()
+            .all(|leg| tokens.behavior(leg.token_in).is_exact());
+        if exact || self.legs.is_empty() {
+            return Settlement {
+                opportunity: self.clone(),
+                unsafe_legs: vec![],
+            };
+        }
+        let mut legs = Vec::with_capacity(self.legs.len());
+        let mut unsafe_legs = vec![];
+        let mut amount_in = self.amount_in;
+        for (i, (leg, (pool, fee))) in self.legs.iter().zip(pools).enumerate() {
+            let arrived = tokens.behavior(leg.token_in).taxed(amount_in);
+            let out = match pool {
+                Pool::V2(pool) => v2::quote_amount_out(arrived, pool, leg.token_in, *fee),
+                Pool::V3(pool) => v3::v3_quote(pool, leg.token_in, arrived, *fee),
+            };
+            let delivered = tokens.behavior(leg.token_in.other()).taxed(out);
+            if delivered < leg.min_out {
+                unsafe_legs.push(i);
+            }
+            legs.push(Leg {
+                amount_in,
+                expected_out: delivered,
+                ..*leg
+            });
+            amount_in = delivered;
+        }
+        let amount_out = tokens.behavior(self.token_in).rebased(amount_in);
+        let gross_profit = amount_out - self.amount_in;
+        Settlement {
+            opportunity: Opportunity {
+                gross_profit,
+                net_profit: gross_profit - self.gas_cost,
+                legs,
+                ..self.clone()
+            },
+            unsafe_legs,
+        }
+    }
+}
+
+// Slippage tolerance and ROI are fractions, so 0.005 is half a percent. Minimum net profit is in units of the input token.
+#[derive(Copy, Clone, Debug)]
+pub struct OpportunityConfig {
+    pub slippage_tolerance: f64,
+    pub min_net_profit: f64,
+    pub min_roi: f64,
+}
+
+impl Default for OpportunityConfig {
+    fn default() -> Self {
+        OpportunityConfig {
+            slippage_tolerance: 0.005,
+            min_net_profit: 0.,
+            min_roi: 0.,
+        }
+    }
+}
+
+impl OpportunityConfig {
+    pub fn leg(&self, venue: Venue, token_in: Token, amount_in: f64, expected_out: f64) -> Leg {
+        Leg {
+            venue,
+            token_in,
+            amount_in,
+            expected_out,
+            min_out: expected_out * (1. - self.slippage_tolerance),
+        }
+    }
+
+    // [accepts] is true when the opportunity clears both the minimum net profit and the minimum ROI.
+    pub fn accepts(&self, opportunity: &Opportunity) -> bool {
+        opportunity.amount_in > 0.
+            && opportunity.net_profit > self.min_net_profit
+            && opportunity.roi() >= self.min_roi
+    }
+
+    pub fn actionable(&self, opportunities: Vec<Opportunity>) -> Vec<Opportunity> {
+        opportunities
+            .into_iter()
+            .filter(|o| self.accepts(o))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::token::{TokenBehavior, TokenInfo};
+
+    fn opportunity(amount_in: f64, net_profit: f64) -> Opportunity {
+        Opportunity {
+            token_in: Token::Eth,
+            amount_in,
+            gross_profit: net_profit + 0.01,
+            gas_cost: 0.01,
+            net_profit,
+            legs: vec![],
+        }
+    }
+
+    #[test]
+    fn min_out_applies_slippage() {
+        let config = OpportunityConfig {
+            slippage_tolerance: 0.01,
+            ..OpportunityConfig::default()
+        };
+
+        let leg = config.leg(Venue::V2, Token::Eth, 1., 2000.);
+
+        assert_eq!(leg.min_out, 1980.);
+    }
+
+    fn v2_pool(dai: f64) -> Pool {
+        Pool::V2(Box::new(v2::Pool::new(Token::Eth, Token::Dai, 1000., dai)))
+    }
+
+    fn quote(pool: &Pool, amount_in: f64, token_in: Token) -> f64 {
+        match pool {
+            Pool::V2(pool) => v2::quote_amount_out(amount_in, pool, token_in, 0.003),
+            Pool::V3(pool) => v3::v3_quote(pool, token_in, amount_in, 0.003),
+        }
+    }
+
+    fn taxed(token: Token, transfer_tax_bps: u32, rebase_multiplier: f64) -> TokenRegistry {
+        TokenRegistry::new(vec![TokenInfo {
+            symbol: "TAX".to_string(),
+            address: "0x01".to_string(),
+            token,
+            behavior: TokenBehavior {
+                transfer_tax_bps,
+                rebase_multiplier,
+            },
+        }])
+    }
+
+    #[test]
+    fn settles_through_taxed_token() {
+        let config = OpportunityConfig::default();
+        let pool1 = v2_pool(2000000.);
+        let pool2 = v2_pool(1800000.);
+        let pools = [(&pool1, 0.003), (&pool2, 0.003)];
+        let mid = quote(&pool1, 1., Token::Eth);
+        let mut quoted = opportunity(1., 0.09);
+        quoted.legs = vec![
+            config.leg(Venue::V2, Token::Eth, 1., mid),
+            config.leg(Venue::V2, Token::Dai, mid, 1.1),
+        ];
+
+        let plain = quoted.settle(&pools, &TokenRegistry::default());
+        let settled = quoted.settle(&pools, &taxed(Token::Dai, 100, 1.));
+
+        assert!(plain.is_safe());
+        assert_eq!(plain.opportunity.gross_profit, quoted.gross_profit);
+        assert!((settled.opportunity.legs[0].expected_out - mid * 0.99).abs() < 1e-9);
+        assert_eq!(
+            settled.opportunity.legs[1].amount_in,
+            settled.opportunity.legs[0].expected_out
+        );
+        let requoted = quote(&pool2, mid * 0.99 * 0.99, Token::Dai);
+        assert!((settled.opportunity.legs[1].expected_out - requoted).abs() < 1e-12);
+        assert_eq!(settled.unsafe_legs, vec![0, 1]);
+        assert_eq!(
+            settled.opportunity.net_profit,
+            settled.opportunity.gross_profit - 0.01
+        );
+    }
+
+    #[test]
+    fn rebase_applies_once_per_settlement() {
+        let config = OpportunityConfig::default();
+        let pool1 = v2_pool(2000000.);
+        let pool2 = v2_pool(1800000.);
+        let pools = [(&pool1, 0.003), (&pool2, 0.003)];
+        let mut quoted = opportunity(1., 0.09);
+        quoted.legs = vec![
+            config.leg(Venue::V2, Token::Eth, 1., 1990.),
+            config.leg(Venue::V2, Token::Dai, 1990., 1.1),
+        ];
+
+        let settled = quoted.settle(&pools, &taxed(Token::Eth, 0, 1.1));
+
+        let legs = &settled.opportunity.legs;
+        let out = quote(&pool2, legs[1].amount_in, Token::Dai);
+        assert_eq!(legs[1].expected_out, out);
+        assert!((settled.opportunity.gross_profit - (out * 1.1 - 1.)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn filters_by_profit_and_roi() {
+        let config = OpportunityConfig {
+            slippage_tolerance: 0.005,
+            min_net_profit: 0.05,
+            min_roi: 0.02,
+        };
+
+        let kept = config.actionable(vec![
+            opportunity(1., 0.1),
+            opportunity(1., -0.1),
+            opportunity(1., 0.01),
+            opportunity(10., 0.1),
+        ]);
+
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].amount_in, 1.);
+        assert_eq!(kept[0].net_profit, 0.1);
+    }
+
+    #[test]
+    #[should_panic(expected = "settle needs one pool per leg")]
+    fn settle_rejects_missing_pool() {
+        let config = OpportunityConfig::default();
+        let pool1 = v2_pool(2000000.);
+        let mut quoted = opportunity(1., 0.09);
+        quoted.legs = vec![
+            config.leg(Venue::V2, Token::Eth, 1., 2000.),
+            config.leg(Venue::V2, Token::Dai, 2000., 1.1),
+        ];
+
+        quoted.settle(&[(&pool1, 0.003)], &TokenRegistry::default());
+    }
+}
@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// [Observation] follows Uniswap V3's Oracle library. seconds_per_liquidity_cumulative is plain seconds per unit of liquidity rather than a Q128 fixed point number, matching the rest of the f64 model.
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug, Default)]
+pub struct Observation {
+    pub block_timestamp: u64,
+    pub tick_cumulative: i64,
+    pub seconds_per_liquidity_cumulative: f64,
+    pub initialized: bool,
+}
+
+impl Observation {
+    // [transform] extends the observation to time, assuming tick and liquidity held since it was written. Zero liquidity counts as one, as in Uniswap. A time before the observation is too old, like Uniswap's OLD revert.
+    pub fn transform(
+        &self,
+        time: u64,
+        tick: i32,
+        liquidity: f64,
+    ) -> Result<Observation, OracleError> {
+        let delta = time
+            .checked_sub(self.block_timestamp)
+            .ok_or(OracleError::TooOld {
+                target: time,
+                oldest: self.block_timestamp,
+            })?;
+        Ok(Observation {
+            block_timestamp: time,
+            tick_cumulative: self.tick_cumulative + tick as i64 * delta as i64,
+            seconds_per_liquidity_cumulative: self.seconds_per_liquidity_cumulative
+                + delta as f64 / liquidity.max(1.),
+            initialized: true,
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum OracleError {
+    TooOld { target: u64, oldest: u64 },
+    InFuture { latest: u64, now: u64 },
+}
+
+impl fmt::Display for OracleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OracleError::TooOld { target, oldest } => write!(
+                f,
+                "no observation at time {}, the oldest is at {}",
+                target, oldest
+            ),
+            OracleError::InFuture { latest, now } => write!(
+                f,
+                "the latest observation at {} is after the current time {}",
+                latest, now
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OracleError {}
+
+// [Oracle] is a ring buffer of observations. It starts with room for one; grow reserves more, and the ring only uses the new slots once it wraps around to them, as in Uniswap.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Oracle {
+    pub observations: Vec<Observation>,
+    pub index: usize,
+    pub cardinality: usize,
+    pub cardinality_next: usize,
+}
+
+// The default oracle is the one a pool starts with at time 0.
+impl Default for Oracle {
+    fn default() -> Self {
+        Oracle::new(0)
+    }
+}
+
+impl Oracle {
+    pub fn new(time: u64) -> Self {
+        Oracle {
+            observations: vec![Observation {
+                block_timestamp: time,
+                initialized: true,
+                ..Observation::default()
+            }],
+            index: 0,
+            cardinality: 1,
+            cardinality_next: 1,
+        }
+    }
+
+    // [write] records the tick and liquidity that held up to time. It writes at most once per timestamp, and never before the latest observation.
+    pub fn write(&mut self, time: u64, tick: i32, liquidity: f64) {
+        let last = self.observations[self.index];
+        let next = match last.transform(time, tick, liquidity) {
+            Ok(next) if time > last.block_timestamp => next,
+            _ => return,
+        };
+        if self.cardinality_next > self.cardinality && self.index == self.cardinality - 1 {
+            self.cardinality = self.cardinality_next;
+        }
+        self.index = (self.index + 1) % self.cardinality;
+        self.observations[self.index] = next;
+    }
+
+    pub fn grow(&mut self, next: usize) {
+        if next <= self.cardinality_next {
+            return;
+        }
+        self.observations.resize(next, Observation::default());
+        self.cardinality_next = next;
+    }
+
+    fn at(&self, i: usize) -> Observation {
+        self.observations[i % self.cardinality]
+    }
+
+    // [observe_single] returns the cumulatives seconds_ago before now, interpolating between the observations either side of it.
+    pub fn observe_single(
+        &self,
+        now: u64,
+        seconds_ago: u64,
+        tick: i32,
+        liquidity: f64,
+    ) -> Result<(i64, f64), OracleError> {
+        let last = self.observations[self.index];
+        if last.block_timestamp > now {
+            return Err(OracleError::InFuture {
+                latest: last.block_timestamp,
+                now,
+            });
+        }
+        let target = match now.checked_sub(seconds_ago) {
+            Some(target) => target,
+            None => {
+                return Err(OracleError::TooOld {
+                    target: 0,
+                    oldest: self.oldest().block_timestamp,
+                })
+            }
+        };
+        if target < last.block_timestamp {
+            return self.interpolate(target);
+        }
+        let at = last.transform(target, tick, liquidity)?;
+        Ok((at.tick_cumulative, at.seconds_per_liquidity_cumulative))
+    }
+
+    pub fn observe(
+        &self,
+        now: u64,
+        seconds_agos: &[u64],
+        tick: i32,
+        liquidity: f64,
+    ) -> Result<Vec<(i64, f64)>, OracleError> {
+        seconds_agos
+            .iter()
+            .map(|seconds_ago| self.observe_single(now, *seconds_ago, tick, liquidity))
+            .collect()
+    }
+
+    fn oldest(&self) -> Observation {
+        let next = self.at(self.index + 1);
+        if next.initialized {
+            next
+        } else {
+            self.observations[0]
+        }
+    }
+
+    // [interpolate] binary searches the ring, oldest first, for the observations around target, which must be before the newest one.
+    fn interpolate(&self, target: u64) -> Result<(i64, f64), OracleError> {
+        let oldest = self.oldest();
+        if target < oldest.block_timestamp {
+            return Err(OracleError::TooOld {
+                target,
+                oldest: oldest.block_timestamp,
+            });
+        }
+        let start = if self.at(self.index + 1).initialized {
+            self.index + 1
+        } else {
+            0
+        };
+        let (mut low, mut high) = (0, self.cardinality - 1);
+        if start == 0 {
+            high = self.index;
+        }
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            if self.at(start + mid).block_timestamp <= target {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        let before = self.at(start + low);
+        if before.block_timestamp == target {
+            return Ok((
+                before.tick_cumulative,
+                before.seconds_per_liquidity_cumulative,
+            ));
+        }
+        let after = self.at(start + low + 1);
+        let span = (after.block_timestamp - before.block_timestamp) as f64;
+        let elapsed = (target - before.block_timestamp) as f64;
+        let tick_cumulative = before.tick_cumulative
+            + ((after.tick_cumulative - before.tick_cumulative) as f64 / span * elapsed) as i64;
+        let seconds_per_liquidity_cumulative = before.seconds_per_liquidity_cumulative
+            + (after.seconds_per_liquidity_cumulative - before.seconds_per_liquidity_cumulative)
+                / span
+                * elapsed;
+        Ok((tick_cumulative, seconds_per_liquidity_cumulative))
+    }
+}
+
+// [time_weighted_tick] is the mean tick over the window seconds before now, from two observe results.
+pub fn time_weighted_tick(start: (i64, f64), end: (i64, f64), window: u64) -> f64 {
+    (end.0 - start.0) as f64 / window as f64
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn accumulates_tick_and_seconds_per_liquidity() {
+        let mut oracle = Oracle::new(100);
+        oracle.grow(4);
+
+        oracle.write(110, 10, 5.);
+        oracle.write(130, -20, 10.);
+
+        let observed = oracle.observe(150, &[0, 40, 45, 50], 5, 2.).unwrap();
+        assert_eq!(observed[0].0, 10 * 10 + -20 * 20 + 5 * 20);
+        assert_eq!(observed[1], (100, 2.));
+        assert_eq!(observed[2].0, 50);
+        assert_eq!(observed[3], (0, 0.));
+        assert_eq!(oracle.cardinality, 4);
+        assert_eq!(
+            time_weighted_tick(observed[1], observed[0], 40),
+            (-400 + 100) as f64 / 40.
+        );
+    }
+
+    #[test]
+    fn cardinality_grows_only_when_the_ring_wraps() {
+        let mut oracle = Oracle::new(0);
+
+        oracle.write(1, 1, 1.);
+        oracle.grow(3);
+        assert_eq!(oracle.cardinality, 1);
+        oracle.write(2, 1, 1.);
+        oracle.write(3, 1, 1.);
+        oracle.write(4, 1, 1.);
+
+        assert_eq!(oracle.cardinality, 3);
+        assert_eq!(
+            oracle.observe_single(4, 3, 1, 1.),
+            Err(OracleError::TooOld {
+                target: 1,
+                oldest: 2
+            })
+        );
+        assert_eq!(oracle.observe_single(4, 2, 1, 1.), Ok((2, 2.)));
+    }
+
+    #[test]
+    fn writes_once_per_timestamp() {
+        let mut oracle = Oracle::new(0);
+        oracle.grow(2);
+
+        oracle.write(5, 7, 1.);
+        oracle.write(5, 100, 1.);
+
+        assert_eq!(oracle.observe_single(5, 0, 0, 1.), Ok((35, 5.)));
+    }
+
+    #[test]
+    fn ignores_writes_before_the_latest_observation() {
+        let mut oracle = Oracle::new(10);
+        oracle.grow(2);
+
+        oracle.write(4, 7, 1.);
+
+        assert_eq!(oracle.index, 0);
+        assert_eq!(
+            oracle.observations[0].transform(4, 7, 1.),
+            Err(OracleError::TooOld {
+                target: 4,
+                oldest: 10
+            })
+        );
+        assert_eq!(oracle.observe_single(12, 0, 3, 1.), Ok((6, 2.)));
+    }
+}
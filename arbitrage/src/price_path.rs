@@ -0,0 +1,316 @@
+use crate::scenario::{Action, Pool, World};
+use crate::simulation::{
+    amount_to_spot, base_token, spot_price, standard_normal, Clock, OrderFlow,
+};
+use crate::token::Token;
+use rand::rngs::StdRng;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// [PricePath] is a reference price, in token_1 per token_0, sampled at increasing times in seconds. Between samples the last price holds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PricePath {
+    pub points: Vec<(u64, f64)>,
+}
+
+impl PricePath {
+    // [gbm] samples geometric Brownian motion every dt seconds. Drift and volatility are per year, as is usual for GBM.
+    pub fn gbm(
+        rng: &mut StdRng,
+        start: f64,
+        drift: f64,
+        volatility: f64,
+        dt: u64,
+        steps: usize,
+    ) -> Self {
+        let years = dt as f64 / (365. * 24. * 60. * 60.);
+        let mut price = start;
+        let mut points = vec![(0, start)];
+        for i in 1..=steps {
+            let shock = volatility * years.sqrt() * standard_normal(rng);
+            price *= ((drift - volatility * volatility / 2.) * years + shock).exp();
+            points.push((i as u64 * dt, price));
+        }
+        PricePath { points }
+    }
+
+    // [from_csv] reads timestamp,price rows. A first row that does not parse is taken as a header.
+    pub fn from_csv<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut points = vec![];
+        for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut fields = line.split(',').map(str::trim);
+            let parsed = match (fields.next(), fields.next()) {
+                (Some(time), Some(price)) => {
+                    time.parse::<u64>().ok().zip(price.parse::<f64>().ok())
+                }
+                _ => None,
+            };
+            match parsed {
+                Some(point) => points.push(point),
+                None if i == 0 => continue,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {} is not timestamp,price: {:?}", i + 1, line),
+                    ))
+                }
+            }
+        }
+        points.sort_by_key(|(time, _)| *time);
+        Ok(PricePath { points })
+    }
+
+    pub fn price_at(&self, time: u64) -> Option<f64> {
+        let after = self.points.partition_point(|(t, _)| *t <= time);
+        if after == 0 {
+            return self.points.first().map(|(_, price)| *price);
+        }
+        Some(self.points[after - 1].1)
+    }
+}
+
+// [PathFollower] trades each pool toward the reference price at the current time. It sizes the trade that would close the whole gap, takes the aggressiveness fraction of it, scales that by 1 + noise * N(0, 1) and caps it at max_amount.
+pub struct PathFollower {
+    pub trader: String,
+    pub pools: Vec<String>,
+    pub path: PricePath,
+    pub aggressiveness: f64,
+    pub noise: f64,
+    pub max_amount: f64,
+}
+
+// [amount_to_target] returns the input token and amount that leaves the pool's spot price at target once swapped, sized by [amount_to_spot] on the curve the swap runs on, or None if it is already there or cannot get there.
+pub fn amount_to_target(pool: &Pool, fee: f64, target: f64) -> Option<(Token, f64)> {
+    let price = spot_price(pool);
+    if price == target || target <= 0. {
+        return None;
+    }
+    let sell_base = price > target;
+    let amount = amount_to_spot(pool, fee, target, sell_base);
+    if !amount.is_finite() || amount <= 0. {
+        return None;
+    }
+    let base = base_token(pool);
+    Some((if sell_base { base } else { base.other() }, amount))
+}
+
+impl OrderFlow for PathFollower {
+    fn next(&mut self, rng: &mut StdRng, world: &World, clock: &Clock) -> Vec<Action> {
+        let target = match self.path.price_at(clock.now) {
+            Some(target) => target,
+            None => return vec![],
+        };
+        let mut actions = vec![];
+        for name in &self.pools {
+            let named = match world.pool(name) {
+                Ok(named) => named,
+                Err(_) => continue,
+            };
+            let (token_in, full) = match amount_to_target(&named.pool, named.fee, target) {
+                Some(trade) => trade,
+                None => continue,
+            };
+            let scale = (1. + self.noise * standard_normal(rng)).max(0.);
+            let amount = (full * self.aggressiveness * scale).min(self.max_amount);
+            if amount <= 0. || !amount.is_finite() {
+                continue;
+            }
+            actions.push(Action::Swap {
+                pool: name.clone(),
+                trader: self.trader.clone(),
+                token_in: format!("{:?}", token_in),
+                amount,
+            });
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::math;
+    use crate::scenario::Scenario;
+    use crate::simulation::Simulation;
+    use crate::v3;
+    use rand::SeedableRng;
+
+    #[test]
+    fn gbm_is_seeded() {
+        let first = PricePath::gbm(&mut StdRng::seed_from_u64(1), 2000., 0., 0.8, 12, 100);
+        let second = PricePath::gbm(&mut StdRng::seed_from_u64(1), 2000., 0., 0.8, 12, 100);
+
+        assert_eq!(first, second);
+        assert_eq!(first.points.len(), 101);
+        assert_eq!(first.points[100].0, 1200);
+        assert!(first.points.iter().all(|(_, price)| *price > 0.));
+        assert_eq!(first.price_at(30), Some(first.points[2].1));
+    }
+
+    #[test]
+    fn reads_csv_with_header() {
+        let path = std::env::temp_dir().join(format!("arbitrage-path-{}.csv", std::process::id()));
+        fs::write(&path, "timestamp,price\n60,2010.5\n0,2000\n").unwrap();
+
+        let read = PricePath::from_csv(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.unwrap().points, vec![(0, 2000.), (60, 2010.5)]);
+    }
+
+    #[test]
+    fn follower_sizes_v2_trade_on_constant_product_curve() {
+        let world = Scenario::from_toml(
+            r#"
+            [[pools]]
+            kind = "v2"
+            name = "pair"
+            token_x = "Eth"
+            token_y = "Dai"
+            reserve_x = 4.0
+            reserve_y = 4000.0
+            fee = 0.003
+
+            [[traders]]
+            name = "cex"
+            id = 1
+            eth = 1000.0
+            dai = 1000000.0
+            "#,
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+        let mut simulation = Simulation::new(world, 1, 12);
+        simulation.add(PathFollower {
+            trader: "cex".to_string(),
+            pools: vec!["pair".to_string()],
+            path: PricePath {
+                points: vec![(0, 800.), (24, 1250.)],
+            },
+            aggressiveness: 1.,
+            noise: 0.,
+            max_amount: f64::MAX,
+        });
+
+        let expected = amount_to_spot(
+            &simulation.world.pool("pair").unwrap().pool,
+            0.003,
+            800.,
+            true,
+        );
+
+        simulation.step().unwrap();
+        let after_drop = spot_price(&simulation.world.pool("pair").unwrap().pool);
+        simulation.step().unwrap();
+        let after_rise = spot_price(&simulation.world.pool("pair").unwrap().pool);
+
+        assert_eq!(
+            simulation.trace[0].action,
+            Action::Swap {
+                pool: "pair".to_string(),
+                trader: "cex".to_string(),
+                token_in: "Eth".to_string(),
+                amount: expected,
+            }
+        );
+        assert!((after_drop - 800.).abs() <= 800. * 1e-9);
+        assert!((after_rise - 1250.).abs() <= 1250. * 1e-9);
+    }
+
+    #[test]
+    fn follower_lands_v3_pool_on_path() {
+        let world = Scenario::from_toml(
+            r#"
+            [[pools]]
+            kind = "v3"
+            name = "pool"
+            token_0 = "Eth"
+            token_1 = "Dai"
+            sqrt_price_x96 = 5602277097478614198912276234240.0
+            fee = 0.003
+
+            [[traders]]
+            name = "lp"
+            id = 1
+            eth = 0.0
+            dai = 0.0
+
+            [[traders]]
+            name = "cex"
+            id = 2
+            eth = 1000000.0
+            dai = 1000000000.0
+
+            [[positions]]
+            pool = "pool"
+            owner = "lp"
+            lower_tick = -86040
+            upper_tick = 86040
+            liquidity = 1000000.0
+
+            [[positions]]
+            pool = "pool"
+            owner = "lp"
+            lower_tick = 8460
+            upper_tick = 8580
+            liquidity = 1000000.0
+            "#,
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+        let mut simulation = Simulation::new(world, 1, 12);
+        simulation.add(PathFollower {
+            trader: "cex".to_string(),
+            pools: vec!["pool".to_string()],
+            path: PricePath {
+                points: vec![(0, 4700.), (24, 5400.)],
+            },
+            aggressiveness: 1.,
+            noise: 0.,
+            max_amount: f64::MAX,
+        });
+
+        simulation.step().unwrap();
+        let after_drop = spot_price(&simulation.world.pool("pool").unwrap().pool);
+        simulation.step().unwrap();
+        let after_rise = spot_price(&simulation.world.pool("pool").unwrap().pool);
+
+        assert!((after_drop - 4700.).abs() <= 4700. * 1e-9);
+        assert!((after_rise - 5400.).abs() <= 5400. * 1e-9);
+    }
+
+    #[test]
+    fn v3_target_walks_initialized_ticks() {
+        let owner = v3::Trader {
+            id: 1,
+            amt_eth: Default::default(),
+            amt_dai: Default::default(),
+        };
+        let mut pool = v3::uniswap_v3_pool::create(Token::Eth, Token::Dai, 0.003).unwrap();
+        pool.initialize(v3::price_to_sqrtp(5000.)).unwrap();
+        pool.mint(&owner, 8460, 8580, 1e18).unwrap();
+        pool.mint(&owner, 8580, 8700, 3e18).unwrap();
+        let target = (v3::tick_to_price(8640) / math::get_q96()).powi(2);
+        let current_range = v3::calc_amount1(
+            1e18,
+            *pool.sqrt_price_x96.read().unwrap(),
+            v3::tick_to_price(8640),
+        ) / 0.997;
+        let swap = pool.swap_to_price(v3::tick_to_price(8640), 0.003).unwrap();
+        let pool = Pool::V3(Box::new(pool));
+
+        let (token_in, amount) = amount_to_target(&pool, 0.003, target).unwrap();
+
+        assert_eq!(token_in, Token::Dai);
+        assert!((amount - swap.amount_in).abs() <= swap.amount_in * 1e-9);
+        assert!(amount > current_range * 1.5);
+        assert_eq!(amount_to_target(&pool, 0.003, f64::INFINITY), None);
+    }
+}
@@ -0,0 +1,457 @@
+use crate::token::{Token, TokenInfo, TokenRegistry};
+use crate::v2;
+use crate::v3::{self, uniswap_v3_pool, PoolError, Trader};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::RwLock;
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    Unknown { kind: &'static str, name: String },
+    Invalid(String),
+    Pool(PoolError),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScenarioError::Io(e) => write!(f, "could not read scenario: {}", e),
+            ScenarioError::Toml(e) => write!(f, "malformed TOML scenario: {}", e),
+            ScenarioError::Json(e) => write!(f, "malformed JSON scenario: {}", e),
+            ScenarioError::Unknown { kind, name } => write!(f, "unknown {} {:?}", kind, name),
+            ScenarioError::Invalid(s) => write!(f, "{}", s),
+            ScenarioError::Pool(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+impl From<PoolError> for ScenarioError {
+    fn from(e: PoolError) -> Self {
+        ScenarioError::Pool(e)
+    }
+}
+
+impl From<std::io::Error> for ScenarioError {
+    fn from(e: std::io::Error) -> Self {
+        ScenarioError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ScenarioError {
+    fn from(e: toml::de::Error) -> Self {
+        ScenarioError::Toml(e)
+    }
+}
+
+impl From<serde_json::Error> for ScenarioError {
+    fn from(e: serde_json::Error) -> Self {
+        ScenarioError::Json(e)
+    }
+}
+
+// Tokens in pools and actions are names resolved through the scenario's token registry, so "WETH" works if it is registered and "Eth" always works. Fees are fractions.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum PoolSpec {
+    V2 {
+        name: String,
+        token_x: String,
+        token_y: String,
+        reserve_x: f64,
+        reserve_y: f64,
+        fee: f64,
+    },
+    V3 {
+        name: String,
+        token_0: String,
+        token_1: String,
+        sqrt_price_x96: f64,
+        fee: f64,
+    },
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct TraderSpec {
+    pub name: String,
+    pub id: i32,
+    #[serde(default)]
+    pub eth: f64,
+    #[serde(default)]
+    pub dai: f64,
+}
+
+// A V3 position minted by owner when the world is built.
+#[derive(Deserialize, Clone, Debug)]
+pub struct PositionSpec {
+    pub pool: String,
+    pub owner: String,
+    pub lower_tick: i32,
+    pub upper_tick: i32,
+    pub liquidity: f64,
+}
+
+// Mint takes a negative liquidity to burn. Add and Remove change V2 reserves directly.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Action {
+    Swap {
+        pool: String,
+        trader: String,
+        token_in: String,
+        amount: f64,
+    },
+    Mint {
+        pool: String,
+        owner: String,
+        lower_tick: i32,
+        upper_tick: i32,
+        liquidity: f64,
+    },
+    Add {
+        pool: String,
+        x: f64,
+        y: f64,
+    },
+    Remove {
+        pool: String,
+        x: f64,
+        y: f64,
+    },
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Scenario {
+    #[serde(default)]
+    pub tokens: Vec<TokenInfo>,
+    #[serde(default)]
+    pub pools: Vec<PoolSpec>,
+    #[serde(default)]
+    pub traders: Vec<TraderSpec>,
+    #[serde(default)]
+    pub positions: Vec<PositionSpec>,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+}
+
+impl Scenario {
+    pub fn from_toml(source: &str) -> Result<Self, ScenarioError> {
+        Ok(toml::from_str(source)?)
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, ScenarioError> {
+        Ok(serde_json::from_str(source)?)
+    }
+
+    // [load] reads JSON from .json files and TOML from anything else.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ScenarioError> {
+        let source = fs::read_to_string(&path)?;
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("json") => Scenario::from_json(&source),
+            _ => Scenario::from_toml(&source),
+        }
+    }
+
+    // [build] creates the pools and traders and mints the positions. Actions are left for World::run so callers can step through them.
+    pub fn build(&self) -> Result<World, ScenarioError> {
+        let mut world = World {
+            tokens: TokenRegistry::new(self.tokens.clone()),
+            pools: vec![],
+            traders: vec![],
+            actions: self.actions.clone(),
+        };
+        for spec in &self.pools {
+            let pool = world.build_pool(spec)?;
+            world.pools.push(pool);
+        }
+        for spec in &self.traders {
+            world.traders.push(NamedTrader {
+                name: spec.name.clone(),
+                trader: Trader {
+                    id: spec.id,
+                    amt_eth: RwLock::new(spec.eth),
+                    amt_dai: RwLock::new(spec.dai),
+                },
+            });
+        }
+        for position in &self.positions {
+            world.apply(&Action::Mint {
+                pool: position.pool.clone(),
+                owner: position.owner.clone(),
+                lower_tick: position.lower_tick,
+                upper_tick: position.upper_tick,
+                liquidity: position.liquidity,
+            })?;
+        }
+        Ok(world)
+    }
+}
+
+pub enum Pool {
+    V2(Box<v2::Pool>),
+    V3(Box<uniswap_v3_pool>),
+}
+
+pub struct NamedPool {
+    pub name: String,
+    pub fee: f64,
+    pub pool: Pool,
+}
+
+pub struct NamedTrader {
+    pub name: String,
+    pub trader: Trader,
+}
+
+pub struct World {
+    pub tokens: TokenRegistry,
+    pub pools: Vec<NamedPool>,
+    pub traders: Vec<NamedTrader>,
+    pub actions: Vec<Action>,
+}
+
+impl World {
+    fn token(&self, name: &str) -> Result<Token, ScenarioError> {
+        self.tokens
+            .resolve(name)
+            .map_err(|_| ScenarioError::Unknown {
+                kind: "token",
+                name: name.to_string(),
+            })
+    }
+
+    fn build_pool(&self, spec: &PoolSpec) -> Result<NamedPool, ScenarioError> {
+        let named = match spec {
+            PoolSpec::V2 {
+                name,
+                token_x,
+                token_y,
+                reserve_x,
+                reserve_y,
+                fee,
+            } => NamedPool {
+                name: name.clone(),
+                fee: *fee,
+                pool: Pool::V2(Box::new(v2::Pool::new(
+                    self.token(token_x)?,
+                    self.token(token_y)?,
+                    *reserve_x,
+                    *reserve_y,
+                ))),
+            },
+            PoolSpec::V3 {
+                name,
+                token_0,
+                token_1,
+                sqrt_price_x96,
+                fee,
+            } => {
+                let pool =
+                    uniswap_v3_pool::create(self.token(token_0)?, self.token(token_1)?, *fee)?;
+                pool.initialize(*sqrt_price_x96)?;
+                NamedPool {
+                    name: name.clone(),
+                    fee: *fee,
+                    pool: Pool::V3(Box::new(pool)),
+                }
+            }
+        };
+        if self.pools.iter().any(|p| p.name == named.name) {
+            return Err(ScenarioError::Invalid(format!(
+                "pool {:?} is defined twice",
+                named.name
+            )));
+        }
+        Ok(named)
+    }
+
+    fn pool_index(&self, name: &str) -> Result<usize, ScenarioError> {
+        self.pools
+            .iter()
+            .position(|p| p.name == name)
+            .ok_or(ScenarioError::Unknown {
+                kind: "pool",
+                name: name.to_string(),
+            })
+    }
+
+    pub fn pool(&self, name: &str) -> Result<&NamedPool, ScenarioError> {
+        Ok(&self.pools[self.pool_index(name)?])
+    }
+
+    pub fn v2_pool(&self, name: &str) -> Result<&v2::Pool, ScenarioError> {
+        match &self.pool(name)?.pool {
+            Pool::V2(pool) => Ok(pool),
+            Pool::V3(_) => Err(ScenarioError::Invalid(format!(
+                "{:?} is not a V2 pool",
+                name
+            ))),
+        }
+    }
+
+    pub fn v3_pool(&self, name: &str) -> Result<&uniswap_v3_pool, ScenarioError> {
+        match &self.pool(name)?.pool {
+            Pool::V3(pool) => Ok(pool),
+            Pool::V2(_) => Err(ScenarioError::Invalid(format!(
+                "{:?} is not a V3 pool",
+                name
+            ))),
+        }
+    }
+
+    pub fn trader(&self, name: &str) -> Result<&Trader, ScenarioError> {
+        self.traders
+            .iter()
+            .find(|t| t.name == name)
+            .map(|t| &t.trader)
+            .ok_or(ScenarioError::Unknown {
+                kind: "trader",
+                name: name.to_string(),
+            })
+    }
+
+    fn trader_index(&self, name: &str) -> Result<usize, ScenarioError> {
+        self.traders
+            .iter()
+            .position(|t| t.name == name)
+            .ok_or(ScenarioError::Unknown {
+                kind: "trader",
+                name: name.to_string(),
+            })
+    }
+
+    pub fn apply(&mut self, action: &Action) -> Result<(), ScenarioError> {
+        match action {
+            Action::Swap {
+                pool,
+                trader,
+                token_in,
+                amount,
+            } => {
+                let token_in = self.token(token_in)?;
+                let pool_index = self.pool_index(pool)?;
+                let trader_index = self.trader_index(trader)?;
+                let named = &self.pools[pool_index];
+                let trader = &mut self.traders[trader_index].trader;
+                match &named.pool {
+                    // V2 swaps run on the constant product curve that sizes them elsewhere. A trader short of token_in does not trade.
+                    Pool::V2(pool) => {
+                        if trader.balance(token_in) >= *amount {
+                            let amount_out =
+                                v2::swap_amount_out(*amount, pool, token_in, named.fee);
+                            trader.deposit(token_in, -*amount);
+                            trader.deposit(token_in.other(), amount_out);
+                        }
+                    }
+                    Pool::V3(pool) => {
+                        v3::v3_swap(trader, pool, token_in, *amount, named.fee)?;
+                    }
+                }
+            }
+            Action::Mint {
+                pool,
+                owner,
+                lower_tick,
+                upper_tick,
+                liquidity,
+            } => {
+                let pool_index = self.pool_index(pool)?;
+                let trader_index = self.trader_index(owner)?;
+                let trader = &self.traders[trader_index].trader;
+                match &mut self.pools[pool_index].pool {
+                    Pool::V3(pool) => pool.mint(trader, *lower_tick, *upper_tick, *liquidity)?,
+                    Pool::V2(_) => {
+                        return Err(ScenarioError::Invalid(format!(
+                            "cannot mint a V3 position in V2 pool {:?}",
+                            self.pools[pool_index].name
+                        )))
+                    }
+                }
+            }
+            Action::Add { pool, x, y } => v2::add_liquidity(self.v2_pool(pool)?, *x, *y),
+            Action::Remove { pool, x, y } => v2::remove_liquidity(self.v2_pool(pool)?, *x, *y),
+        }
+        Ok(())
+    }
+
+    // [run] applies every scenario action in order.
+    pub fn run(&mut self) -> Result<(), ScenarioError> {
+        for action in self.actions.clone() {
+            self.apply(&action)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const TWO_POOLS: &str = include_str!("../scenarios/two_v3_pools.toml");
+
+    #[test]
+    fn builds_world_from_toml() {
+        let mut world = Scenario::from_toml(TWO_POOLS).unwrap().build().unwrap();
+        let pool1 = world.v3_pool("pool1").unwrap();
+
+        assert_eq!(*pool1.liquidity.read().unwrap(), 100000000000000.);
+        assert_eq!(*pool1.tick.read().unwrap(), 8521);
+        assert_eq!(world.tokens.resolve("WETH"), Ok(Token::Eth));
+
+        let eth_before = world.trader("searcher").unwrap().balance(Token::Eth);
+        world.run().unwrap();
+
+        assert!(world.trader("searcher").unwrap().balance(Token::Eth) < eth_before);
+        assert_eq!(
+            *world.v3_pool("pool2").unwrap().liquidity.read().unwrap(),
+            1000000000000000000. + 20000.
+        );
+    }
+
+    #[test]
+    fn json_and_v2_pools() {
+        let scenario = Scenario::from_json(
+            r#"{
+                "pools": [{"kind": "v2", "name": "sushi", "token_x": "Eth", "token_y": "Dai",
+                           "reserve_x": 4, "reserve_y": 4000, "fee": 0.003}],
+                "actions": [{"type": "add", "pool": "sushi", "x": 1, "y": 1000}]
+            }"#,
+        )
+        .unwrap();
+        let mut world = scenario.build().unwrap();
+
+        world.run().unwrap();
+
+        assert_eq!(*world.v2_pool("sushi").unwrap().x.read().unwrap(), 5.);
+        assert_eq!(*world.v2_pool("sushi").unwrap().y.read().unwrap(), 5000.);
+    }
+
+    #[test]
+    fn reports_unknown_names() {
+        let scenario = Scenario::from_toml(
+            r#"
+            [[pools]]
+            kind = "v2"
+            name = "sushi"
+            token_x = "Eth"
+            token_y = "USDC"
+            reserve_x = 4.0
+            reserve_y = 4000.0
+            fee = 0.003
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            scenario.build(),
+            Err(ScenarioError::Unknown { kind: "token", name }) if name == "USDC"
+        ));
+    }
+}
@@ -0,0 +1,385 @@
+use crate::math;
+use crate::scenario::{Action, Pool, ScenarioError, World};
+use crate::token::Token;
+use crate::v3::price_to_sqrtp;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+
+// [Clock] is simulated time in seconds. Nothing in a simulation reads the wall clock.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default, Serialize)]
+pub struct Clock {
+    pub now: u64,
+}
+
+impl Clock {
+    pub fn advance(&mut self, seconds: u64) {
+        self.now += seconds;
+    }
+}
+
+// [OrderFlow] proposes the actions one participant takes in a step. Generators may only draw randomness from rng, so a seed fixes the whole trace.
+pub trait OrderFlow {
+    fn next(&mut self, rng: &mut StdRng, world: &World, clock: &Clock) -> Vec<Action>;
+}
+
+// [spot_price] is the price of token_0 (V3) or token_x (V2) in the other token, before fees.
+pub fn spot_price(pool: &Pool) -> f64 {
+    match pool {
+        Pool::V2(pool) => *pool.y.read().unwrap() / *pool.x.read().unwrap(),
+        Pool::V3(pool) => (*pool.sqrt_price_x96.read().unwrap() / math::get_q96()).powi(2),
+    }
+}
+
+// [base_token] is the token spot_price is quoted for: token_0 (V3) or token_x (V2).
+pub fn base_token(pool: &Pool) -> Token {
+    match pool {
+        Pool::V2(pool) => pool.token_x,
+        Pool::V3(pool) => pool.token_0,
+    }
+}
+
+// [amount_to_price] is the input that moves spot_price to target: the constant product curve of [v2::quote_amount_out] for V2, the tick walk of [swap_to_price] for V3. sell_base swaps base_token in and pushes the price down. It is infinite when a V3 pool cannot reach target.
+pub fn amount_to_price(pool: &Pool, fee: f64, target: f64, sell_base: bool) -> f64 {
+    match pool {
+        Pool::V2(pool) => {
+            let x = *pool.x.read().unwrap();
+            let y = *pool.y.read().unwrap();
+            if sell_base {
+                ((x * y / target).sqrt() - x) / (1. - fee)
+            } else {
+                ((x * y * target).sqrt() - y) / (1. - fee)
+            }
+        }
+        Pool::V3(pool) => pool
+            .swap_to_price(price_to_sqrtp(target), fee)
+            .map_or(f64::INFINITY, |swap| swap.amount_in),
+    }
+}
+
+// [amount_to_spot] is the input that leaves spot_price at target after the swap has run. V2 keeps the fee in its reserves, as [v2::swap_amount_out] does, so the input solves F*a^2 + (1 + F)*R*a + R^2 - R*R'/P = 0 for the input reserve R, the output reserve R' and the price P in output per input, with F = 1 - fee. V3 takes the fee off the output, so its spot lands where [amount_to_price] aims.
+pub fn amount_to_spot(pool: &Pool, fee: f64, target: f64, sell_base: bool) -> f64 {
+    match pool {
+        Pool::V2(v2_pool) => {
+            let x = *v2_pool.x.read().unwrap();
+            let y = *v2_pool.y.read().unwrap();
+            let (reserve_in, reserve_out, price) = if sell_base {
+                (x, y, target)
+            } else {
+                (y, x, 1. / target)
+            };
+            let keep = 1. - fee;
+            let b = (1. + keep) * reserve_in;
+            let c = reserve_in * reserve_in - reserve_in * reserve_out / price;
+            // The root of the quadratic written to avoid cancellation when c is small.
+            -2. * c / (b + (b * b - 4. * keep * c).sqrt())
+        }
+        Pool::V3(_) => amount_to_price(pool, fee, target, sell_base),
+    }
+}
+
+// [standard_normal] draws from N(0, 1) with the Box-Muller transform.
+pub fn standard_normal(rng: &mut StdRng) -> f64 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.);
+    let u2: f64 = rng.gen();
+    (-2. * u1.ln()).sqrt() * (2. * std::f64::consts::PI * u2).cos()
+}
+
+// [NoiseTrader] swaps a uniformly random amount in a random direction on a random pool, with the given probability each step.
+pub struct NoiseTrader {
+    pub trader: String,
+    pub pools: Vec<String>,
+    pub probability: f64,
+    pub min_amount: f64,
+    pub max_amount: f64,
+}
+
+impl OrderFlow for NoiseTrader {
+    fn next(&mut self, rng: &mut StdRng, world: &World, _clock: &Clock) -> Vec<Action> {
+        if self.pools.is_empty() || !rng.gen_bool(self.probability) {
+            return vec![];
+        }
+        let pool = &self.pools[rng.gen_range(0..self.pools.len())];
+        let token_in = match world.pool(pool) {
+            Ok(named) if rng.gen_bool(0.5) => base_token(&named.pool),
+            Ok(named) => base_token(&named.pool).other(),
+            Err(_) => return vec![],
+        };
+        vec![Action::Swap {
+            pool: pool.clone(),
+            trader: self.trader.clone(),
+            token_in: format!("{:?}", token_in),
+            amount: rng.gen_range(self.min_amount..=self.max_amount),
+        }]
+    }
+}
+
+// [LiquidityProvider] adds or removes a random amount of liquidity with the given probability each step. On a V3 pool it mints or burns its position over [lower_tick, upper_tick], never burning more than it holds. On a V2 pool it adds or removes reserves at the current ratio, sized in units of token_x.
+pub struct LiquidityProvider {
+    pub owner: String,
+    pub pool: String,
+    pub probability: f64,
+    pub lower_tick: i32,
+    pub upper_tick: i32,
+    pub max_liquidity: f64,
+}
+
+impl OrderFlow for LiquidityProvider {
+    fn next(&mut self, rng: &mut StdRng, world: &World, _clock: &Clock) -> Vec<Action> {
+        if !rng.gen_bool(self.probability) {
+            return vec![];
+        }
+        let adding = rng.gen_bool(0.5);
+        let amount = rng.gen_range(0. ..=self.max_liquidity);
+        let named = match world.pool(&self.pool) {
+            Ok(named) => named,
+            Err(_) => return vec![],
+        };
+        match &named.pool {
+            Pool::V3(pool) => {
+                let held = match world.trader(&self.owner) {
+                    Ok(owner) => pool
+                        .position_mapping
+                        .read()
+                        .unwrap()
+                        .get(&owner.id)
+                        .map_or(0., |p| *p.liquidity.read().unwrap()),
+                    Err(_) => return vec![],
+                };
+                let liquidity = if adding { amount } else { -amount.min(held) };
+                if liquidity == 0. {
+                    return vec![];
+                }
+                vec![Action::Mint {
+                    pool: self.pool.clone(),
+                    owner: self.owner.clone(),
+                    lower_tick: self.lower_tick,
+                    upper_tick: self.upper_tick,
+                    liquidity,
+                }]
+            }
+            Pool::V2(pool) => {
+                let x = *pool.x.read().unwrap();
+                let x_amount = if adding { amount } else { amount.min(x / 2.) };
+                let y_amount = x_amount * spot_price(&named.pool);
+                let action = if adding {
+                    Action::Add {
+                        pool: self.pool.clone(),
+                        x: x_amount,
+                        y: y_amount,
+                    }
+                } else {
+                    Action::Remove {
+                        pool: self.pool.clone(),
+                        x: x_amount,
+                        y: y_amount,
+                    }
+                };
+                vec![action]
+            }
+        }
+    }
+}
+
+// [InformedTrader] privately tracks a fair price that follows a geometric random walk with per-step volatility, and trades amount into any pool whose spot price is more than threshold (a fraction) away from it.
+pub struct InformedTrader {
+    pub trader: String,
+    pub pools: Vec<String>,
+    pub fair_price: f64,
+    pub volatility: f64,
+    pub threshold: f64,
+    pub amount: f64,
+}
+
+impl OrderFlow for InformedTrader {
+    fn next(&mut self, rng: &mut StdRng, world: &World, _clock: &Clock) -> Vec<Action> {
+        self.fair_price *= (self.volatility * standard_normal(rng)).exp();
+        let mut actions = vec![];
+        for name in &self.pools {
+            let pool = match world.pool(name) {
+                Ok(named) => &named.pool,
+                Err(_) => continue,
+            };
+            let deviation = spot_price(pool) / self.fair_price - 1.;
+            if deviation.abs() <= self.threshold {
+                continue;
+            }
+            let base = base_token(pool);
+            let token_in = if deviation > 0. { base } else { base.other() };
+            actions.push(Action::Swap {
+                pool: name.clone(),
+                trader: self.trader.clone(),
+                token_in: format!("{:?}", token_in),
+                amount: self.amount,
+            });
+        }
+        actions
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TraceEntry {
+    pub time: u64,
+    pub action: Action,
+}
+
+// [Simulation] steps a World forward on a virtual clock. Generators run in the order they were added and each action is applied before the next generator looks at the world.
+pub struct Simulation {
+    pub world: World,
+    pub clock: Clock,
+    pub step_seconds: u64,
+    pub trace: Vec<TraceEntry>,
+    rng: StdRng,
+    generators: Vec<Box<dyn OrderFlow>>,
+}
+
+impl Simulation {
+    pub fn new(world: World, seed: u64, step_seconds: u64) -> Self {
+        Simulation {
+            world,
+            clock: Clock::default(),
+            step_seconds,
+            trace: vec![],
+            rng: StdRng::seed_from_u64(seed),
+            generators: vec![],
+        }
+    }
+
+    pub fn add<G: OrderFlow + 'static>(&mut self, generator: G) {
+        self.generators.push(Box::new(generator));
+    }
+
+    // [step] advances the clock, and every pool's clock with it, and returns the number of actions applied.
+    pub fn step(&mut self) -> Result<usize, ScenarioError> {
+        self.clock.advance(self.step_seconds);
+        for named in &self.world.pools {
+            match &named.pool {
+                Pool::V2(pool) => pool.set_block_timestamp(self.clock.now),
+                Pool::V3(pool) => pool.set_block_timestamp(self.clock.now),
+            }
+        }
+        let mut applied = 0;
+        for generator in self.generators.iter_mut() {
+            for action in generator.next(&mut self.rng, &self.world, &self.clock) {
+                self.world.apply(&action)?;
+                self.trace.push(TraceEntry {
+                    time: self.clock.now,
+                    action,
+                });
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    pub fn run(&mut self, steps: usize) -> Result<usize, ScenarioError> {
+        let mut applied = 0;
+        for _ in 0..steps {
+            applied += self.step()?;
+        }
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::scenario::Scenario;
+    use crate::snapshot::V3PoolSnapshot;
+
+    fn simulation(seed: u64) -> Simulation {
+        let world = Scenario::from_toml(include_str!("../scenarios/two_v3_pools.toml"))
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut simulation = Simulation::new(world, seed, 12);
+        simulation.add(NoiseTrader {
+            trader: "searcher".to_string(),
+            pools: vec!["pool1".to_string(), "pool2".to_string()],
+            probability: 0.8,
+            min_amount: 1.,
+            max_amount: 20000.,
+        });
+        simulation.add(LiquidityProvider {
+            owner: "lp".to_string(),
+            pool: "pool1".to_string(),
+            probability: 0.5,
+            lower_tick: -86040,
+            upper_tick: 86040,
+            max_liquidity: 20000.,
+        });
+        simulation.add(InformedTrader {
+            trader: "searcher".to_string(),
+            pools: vec!["pool2".to_string()],
+            fair_price: 5000.,
+            volatility: 0.01,
+            threshold: 0.005,
+            amount: 10000.,
+        });
+        simulation
+    }
+
+    fn pool_state(simulation: &Simulation, name: &str) -> V3PoolSnapshot {
+        simulation.world.v3_pool(name).unwrap().into()
+    }
+
+    #[test]
+    fn same_seed_same_trace() {
+        let mut first = simulation(7);
+        let mut second = simulation(7);
+
+        first.run(50).unwrap();
+        second.run(50).unwrap();
+
+        assert!(!first.trace.is_empty());
+        assert_eq!(first.trace, second.trace);
+        assert_eq!(first.clock.now, 600);
+        assert_eq!(pool_state(&first, "pool1"), pool_state(&second, "pool1"));
+        assert_eq!(pool_state(&first, "pool2"), pool_state(&second, "pool2"));
+    }
+
+    #[test]
+    fn different_seeds_diverge() {
+        let mut first = simulation(7);
+        let mut second = simulation(8);
+
+        first.run(50).unwrap();
+        second.run(50).unwrap();
+
+        assert_ne!(first.trace, second.trace);
+    }
+
+    #[test]
+    fn pools_follow_the_clock() {
+        let mut simulation = simulation(5);
+        simulation
+            .world
+            .v3_pool("pool1")
+            .unwrap()
+            .increase_observation_cardinality_next(100);
+
+        simulation.run(20).unwrap();
+
+        let pool = simulation.world.v3_pool("pool1").unwrap();
+        assert_eq!(*pool.block_timestamp.read().unwrap(), 240);
+        let observed = pool.observe(&[0, 120]).unwrap();
+        assert_eq!(observed.len(), 2);
+        assert!(observed[0].1 > observed[1].1);
+    }
+
+    #[test]
+    fn provider_never_burns_more_than_it_holds() {
+        let mut simulation = simulation(3);
+        let lp = simulation.world.trader("lp").unwrap().id;
+
+        simulation.run(200).unwrap();
+
+        let pool = simulation.world.v3_pool("pool1").unwrap();
+        let held = *pool.position_mapping.read().unwrap()[&lp]
+            .liquidity
+            .read()
+            .unwrap();
+        assert!(held >= 0.);
+    }
+}
@@ -0,0 +1,501 @@
+use crate::oracle::Oracle;
+use crate::token::Token;
+use crate::v2;
+use crate::v3::{uniswap_v3_pool, Position, Tick};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::RwLock;
+
+// Bump SNAPSHOT_VERSION whenever the layout below changes, and give each new field a serde default that matches a freshly built pool.
+pub const SNAPSHOT_VERSION: u32 = 6;
+
+// JSON snapshots back to MIN_SNAPSHOT_VERSION load and are upgraded. Binary snapshots are not self-describing, so they must be SNAPSHOT_VERSION.
+pub const MIN_SNAPSHOT_VERSION: u32 = 1;
+
+// Binary snapshots start with MAGIC so a file can be told apart from JSON without trusting its extension.
+pub const MAGIC: &[u8; 4] = b"ARBS";
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    Version(u32),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "could not access snapshot: {}", e),
+            SnapshotError::Json(e) => write!(f, "malformed JSON snapshot: {}", e),
+            SnapshotError::Binary(e) => write!(f, "malformed binary snapshot: {}", e),
+            SnapshotError::Version(v) => write!(
+                f,
+                "snapshot version {} is not supported, expected {} to {} for JSON or {} for binary",
+                v, MIN_SNAPSHOT_VERSION, SNAPSHOT_VERSION, SNAPSHOT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(e: serde_json::Error) -> Self {
+        SnapshotError::Json(e)
+    }
+}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(e: bincode::Error) -> Self {
+        SnapshotError::Binary(e)
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Format {
+    Json,
+    Binary,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct V2PoolSnapshot {
+    pub token_x: Token,
+    pub token_y: Token,
+    pub x: f64,
+    pub y: f64,
+    pub k: f64,
+    #[serde(default)]
+    pub block_timestamp: u64,
+    #[serde(default)]
+    pub block_timestamp_last: u64,
+    #[serde(default)]
+    pub price_0_cumulative_last: f64,
+    #[serde(default)]
+    pub price_1_cumulative_last: f64,
+    #[serde(default)]
+    pub protocol_fee: f64,
+    #[serde(default)]
+    pub k_last: f64,
+    #[serde(default)]
+    pub protocol_fees: (f64, f64),
+}
+
+impl From<&v2::Pool> for V2PoolSnapshot {
+    fn from(pool: &v2::Pool) -> Self {
+        V2PoolSnapshot {
+            token_x: pool.token_x,
+            token_y: pool.token_y,
+            x: *pool.x.read().unwrap(),
+            y: *pool.y.read().unwrap(),
+            k: *pool.k.read().unwrap(),
+            block_timestamp: *pool.block_timestamp.read().unwrap(),
+            block_timestamp_last: *pool.block_timestamp_last.read().unwrap(),
+            price_0_cumulative_last: *pool.price_0_cumulative_last.read().unwrap(),
+            price_1_cumulative_last: *pool.price_1_cumulative_last.read().unwrap(),
+            protocol_fee: *pool.protocol_fee.read().unwrap(),
+            k_last: *pool.k_last.read().unwrap(),
+            protocol_fees: *pool.protocol_fees.read().unwrap(),
+        }
+    }
+}
+
+impl V2PoolSnapshot {
+    pub fn to_pool(&self) -> v2::Pool {
+        v2::Pool {
+            token_x: self.token_x,
+            token_y: self.token_y,
+            x: RwLock::new(self.x),
+            y: RwLock::new(self.y),
+            k: RwLock::new(self.k),
+            block_timestamp: RwLock::new(self.block_timestamp),
+            block_timestamp_last: RwLock::new(self.block_timestamp_last),
+            price_0_cumulative_last: RwLock::new(self.price_0_cumulative_last),
+            price_1_cumulative_last: RwLock::new(self.price_1_cumulative_last),
+            protocol_fee: RwLock::new(self.protocol_fee),
+            k_last: RwLock::new(self.k_last),
+            protocol_fees: RwLock::new(self.protocol_fees),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct TickSnapshot {
+    pub tick: i32,
+    pub liquidity: f64,
+    #[serde(default)]
+    pub liquidity_net: f64,
+    pub initialized: bool,
+}
+
+// Maps are stored as vectors sorted by key, so the same pool always serializes to the same bytes and JSON keys stay numeric.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct V3PoolSnapshot {
+    pub token_0: Token,
+    pub token_1: Token,
+    pub min_tick: i32,
+    pub max_tick: i32,
+    pub balance_0: f64,
+    pub balance_1: f64,
+    pub sqrt_price_x96: f64,
+    pub tick: i32,
+    pub liquidity: f64,
+    pub ticks: Vec<TickSnapshot>,
+    pub liquidity_net: Vec<(i32, f64)>,
+    pub positions: Vec<(i32, f64)>,
+    #[serde(default)]
+    pub block_timestamp: u64,
+    #[serde(default)]
+    pub oracle: Oracle,
+    #[serde(default)]
+    pub fee_protocol: (u8, u8),
+    #[serde(default)]
+    pub protocol_fees: (f64, f64),
+    #[serde(default)]
+    pub fee: f64,
+    #[serde(default = "default_tick_spacing")]
+    pub tick_spacing: i32,
+}
+
+// [default_tick_spacing] is the spacing of a pool built with uniswap_v3_pool::new.
+fn default_tick_spacing() -> i32 {
+    1
+}
+
+impl From<&uniswap_v3_pool> for V3PoolSnapshot {
+    fn from(pool: &uniswap_v3_pool) -> Self {
+        let mut ticks: Vec<TickSnapshot> = pool
+            .tick_mapping
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(tick, t)| TickSnapshot {
+                tick: *tick,
+                liquidity: *t.liquidity.read().unwrap(),
+                liquidity_net: *t.liquidity_net.read().unwrap(),
+                initialized: *t.initialized.read().unwrap(),
+            })
+            .collect();
+        ticks.sort_by_key(|t| t.tick);
+        let mut liquidity_net: Vec<(i32, f64)> = pool
+            .liquidity_mapping
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(tick, liquidity)| (*tick, *liquidity))
+            .collect();
+        liquidity_net.sort_by_key(|(tick, _)| *tick);
+        let mut positions: Vec<(i32, f64)> = pool
+            .position_mapping
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(owner, p)| (*owner, *p.liquidity.read().unwrap()))
+            .collect();
+        positions.sort_by_key(|(owner, _)| *owner);
+        V3PoolSnapshot {
+            token_0: pool.token_0,
+            token_1: pool.token_1,
+            min_tick: pool.min_tick,
+            max_tick: pool.max_tick,
+            balance_0: *pool.balance_0.read().unwrap(),
+            balance_1: *pool.balance_1.read().unwrap(),
+            sqrt_price_x96: *pool.sqrt_price_x96.read().unwrap(),
+            tick: *pool.tick.read().unwrap(),
+            liquidity: *pool.liquidity.read().unwrap(),
+            ticks,
+            liquidity_net,
+            positions,
+            block_timestamp: *pool.block_timestamp.read().unwrap(),
+            oracle: pool.oracle.read().unwrap().clone(),
+            fee_protocol: *pool.fee_protocol.read().unwrap(),
+            protocol_fees: *pool.protocol_fees.read().unwrap(),
+            fee: pool.fee,
+            tick_spacing: pool.tick_spacing,
+        }
+    }
+}
+
+impl V3PoolSnapshot {
+    pub fn to_pool(&self) -> uniswap_v3_pool {
+        let tick_mapping: HashMap<i32, Tick> = self
+            .ticks
+            .iter()
+            .map(|t| {
+                (
+                    t.tick,
+                    Tick {
+                        liquidity: RwLock::new(t.liquidity),
+                        liquidity_net: RwLock::new(t.liquidity_net),
+                        initialized: RwLock::new(t.initialized),
+                    },
+                )
+            })
+            .collect();
+        let position_mapping: HashMap<i32, Position> = self
+            .positions
+            .iter()
+            .map(|(owner, liquidity)| {
+                (
+                    *owner,
+                    Position {
+                        liquidity: RwLock::new(*liquidity),
+                    },
+                )
+            })
+            .collect();
+        uniswap_v3_pool {
+            token_0: self.token_0,
+            token_1: self.token_1,
+            min_tick: self.min_tick,
+            max_tick: self.max_tick,
+            balance_0: RwLock::new(self.balance_0),
+            balance_1: RwLock::new(self.balance_1),
+            tick_mapping: RwLock::new(tick_mapping),
+            liquidity_mapping: RwLock::new(self.liquidity_net.iter().copied().collect()),
+            position_mapping: RwLock::new(position_mapping),
+            sqrt_price_x96: RwLock::new(self.sqrt_price_x96),
+            tick: RwLock::new(self.tick),
+            liquidity: RwLock::new(self.liquidity),
+            block_timestamp: RwLock::new(self.block_timestamp),
+            oracle: RwLock::new(self.oracle.clone()),
+            fee_protocol: RwLock::new(self.fee_protocol),
+            protocol_fees: RwLock::new(self.protocol_fees),
+            fee: self.fee,
+            tick_spacing: self.tick_spacing,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub enum PoolSnapshot {
+    V2(V2PoolSnapshot),
+    V3(V3PoolSnapshot),
+}
+
+// [Snapshot] is the state of a set of pools as of the end of block_number, so a replay can resume from block_number + 1.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Snapshot {
+    pub version: u32,
+    pub block_number: u64,
+    pub pools: Vec<PoolSnapshot>,
+}
+
+impl Snapshot {
+    pub fn new(block_number: u64) -> Self {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            block_number,
+            pools: vec![],
+        }
+    }
+
+    pub fn push_v2(&mut self, pool: &v2::Pool) {
+        self.pools.push(PoolSnapshot::V2(pool.into()));
+    }
+
+    pub fn push_v3(&mut self, pool: &uniswap_v3_pool) {
+        self.pools.push(PoolSnapshot::V3(pool.into()));
+    }
+
+    // [checked] accepts JSON from MIN_SNAPSHOT_VERSION on. Serde has already filled in the fields added since, so the snapshot is upgraded to SNAPSHOT_VERSION.
+    fn checked(mut self) -> Result<Self, SnapshotError> {
+        if !(MIN_SNAPSHOT_VERSION..=SNAPSHOT_VERSION).contains(&self.version) {
+            return Err(SnapshotError::Version(self.version));
+        }
+        // Version 1 came from a model that crossed ticks by their gross liquidity, so its ticks keep doing that.
+        if self.version == 1 {
+            for pool in &mut self.pools {
+                if let PoolSnapshot::V3(pool) = pool {
+                    for tick in &mut pool.ticks {
+                        tick.liquidity_net = tick.liquidity;
+                    }
+                }
+            }
+        }
+        self.version = SNAPSHOT_VERSION;
+        Ok(self)
+    }
+
+    pub fn to_json(&self) -> Result<String, SnapshotError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SnapshotError> {
+        serde_json::from_str::<Snapshot>(json)?.checked()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        let mut bytes = MAGIC.to_vec();
+        bincode::serialize_into(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    // [from_bytes] accepts either format, using the magic prefix to pick the decoder. The binary version is the payload's first field and is checked before the rest is decoded.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        match bytes.strip_prefix(MAGIC) {
+            Some(payload) => {
+                let version: u32 = bincode::deserialize(payload)?;
+                if version != SNAPSHOT_VERSION {
+                    return Err(SnapshotError::Version(version));
+                }
+                Ok(bincode::deserialize(payload)?)
+            }
+            None => serde_json::from_slice::<Snapshot>(bytes)?.checked(),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: Format) -> Result<(), SnapshotError> {
+        let bytes = match format {
+            Format::Json => self.to_json()?.into_bytes(),
+            Format::Binary => self.to_bytes()?,
+        };
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Snapshot::from_bytes(&fs::read(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::v3::Trader;
+
+    fn snapshot() -> Snapshot {
+        let trader = Trader {
+            id: 2,
+            amt_eth: RwLock::new(10000000000.),
+            amt_dai: RwLock::new(10000000000.),
+        };
+        let mut v3_pool =
+            uniswap_v3_pool::new(Token::Eth, Token::Dai, 5602277097478614198912276234240.);
+        v3_pool.mint(&trader, -86000, 86000, 1000000.).unwrap();
+        v3_pool.mint(&trader, 8422, 8613, 250000.).unwrap();
+        let v2_pool = v2::Pool::new(Token::Eth, Token::Dai, 4., 20000.);
+
+        let mut snapshot = Snapshot::new(17000000);
+        snapshot.push_v3(&v3_pool);
+        snapshot.push_v2(&v2_pool);
+        snapshot
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let snapshot = snapshot();
+
+        let restored = Snapshot::from_json(&snapshot.to_json().unwrap()).unwrap();
+
+        assert_eq!(restored, snapshot);
+    }
+
+    #[test]
+    fn round_trips_through_binary() {
+        let snapshot = snapshot();
+        let bytes = snapshot.to_bytes().unwrap();
+
+        assert!(bytes.starts_with(MAGIC));
+        assert!(bytes.len() < snapshot.to_json().unwrap().len());
+        assert_eq!(Snapshot::from_bytes(&bytes).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn restored_pool_matches_original() {
+        let snapshot = snapshot();
+        let PoolSnapshot::V3(v3) = &snapshot.pools[0] else {
+            panic!("expected a V3 pool first");
+        };
+
+        let pool = v3.to_pool();
+
+        assert_eq!(V3PoolSnapshot::from(&pool), *v3);
+        assert_eq!(*pool.liquidity.read().unwrap(), 1250000.);
+        assert_eq!(
+            *pool.position_mapping.read().unwrap()[&2]
+                .liquidity
+                .read()
+                .unwrap(),
+            1250000.
+        );
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut snapshot = snapshot();
+        snapshot.version = SNAPSHOT_VERSION + 1;
+
+        let json = Snapshot::from_json(&snapshot.to_json().unwrap());
+        let binary = Snapshot::from_bytes(&snapshot.to_bytes().unwrap());
+
+        assert!(matches!(json, Err(SnapshotError::Version(v)) if v == SNAPSHOT_VERSION + 1));
+        assert!(matches!(binary, Err(SnapshotError::Version(_))));
+    }
+
+    // [older_json] is the snapshot as an earlier version wrote it, without the fields added since.
+    fn older_json(snapshot: &Snapshot, version: u32, added: &[&str]) -> String {
+        let mut json: serde_json::Value =
+            serde_json::from_str(&snapshot.to_json().unwrap()).unwrap();
+        json["version"] = version.into();
+        for pool in json["pools"].as_array_mut().unwrap() {
+            for fields in pool.as_object_mut().unwrap().values_mut() {
+                for field in added {
+                    fields.as_object_mut().unwrap().remove(*field);
+                }
+            }
+        }
+        json.to_string()
+    }
+
+    #[test]
+    fn loads_older_json_with_defaults() {
+        let snapshot = snapshot();
+        let json = older_json(
+            &snapshot,
+            2,
+            &[
+                "block_timestamp",
+                "block_timestamp_last",
+                "price_0_cumulative_last",
+                "price_1_cumulative_last",
+                "oracle",
+                "protocol_fee",
+                "k_last",
+                "fee_protocol",
+                "protocol_fees",
+                "fee",
+                "tick_spacing",
+            ],
+        );
+
+        assert_eq!(Snapshot::from_json(&json).unwrap(), snapshot);
+        let mut v1: serde_json::Value =
+            serde_json::from_str(&older_json(&snapshot, 1, &[])).unwrap();
+        for tick in v1["pools"][0]["V3"]["ticks"].as_array_mut().unwrap() {
+            tick.as_object_mut().unwrap().remove("liquidity_net");
+        }
+        let v1 = Snapshot::from_json(&v1.to_string()).unwrap();
+        let PoolSnapshot::V3(pool) = &v1.pools[0] else {
+            panic!("expected a V3 pool first");
+        };
+        assert_eq!(v1.version, SNAPSHOT_VERSION);
+        assert!(pool.ticks.iter().all(|t| t.liquidity_net == t.liquidity));
+        let mut binary = snapshot.clone();
+        binary.version = SNAPSHOT_VERSION - 1;
+        assert!(matches!(
+            Snapshot::from_bytes(&binary.to_bytes().unwrap()),
+            Err(SnapshotError::Version(v)) if v == SNAPSHOT_VERSION - 1
+        ));
+    }
+}
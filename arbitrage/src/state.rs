@@ -0,0 +1,215 @@
+use crate::bus::{PoolId, PoolUpdated, UpdateBus};
+use arc_swap::ArcSwap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// A published PoolState is never written again, so readers of a snapshot see one consistent pool even if its fields are behind locks.
+pub struct PoolState<P> {
+    pub version: u64,
+    pub pool: P,
+}
+
+// [SharedPool] publishes pool updates by swapping in a new PoolState. Readers load the current snapshot without locking, and writers are serialized with each other but never wait on readers.
+pub struct SharedPool<P> {
+    current: ArcSwap<PoolState<P>>,
+    writer: Mutex<()>,
+    bus: Option<(PoolId, Arc<UpdateBus>)>,
+}
+
+impl<P: Clone> SharedPool<P> {
+    pub fn new(pool: P) -> Self {
+        SharedPool {
+            current: ArcSwap::from_pointee(PoolState { version: 0, pool }),
+            writer: Mutex::new(()),
+            bus: None,
+        }
+    }
+
+    // [with_bus] is [new] for a pool that announces every update on bus under pool_id.
+    pub fn with_bus(pool: P, pool_id: PoolId, bus: Arc<UpdateBus>) -> Self {
+        SharedPool {
+            bus: Some((pool_id, bus)),
+            ..SharedPool::new(pool)
+        }
+    }
+
+    pub fn snapshot(&self) -> Arc<PoolState<P>> {
+        self.current.load_full()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.current.load().version
+    }
+
+    // [update] applies f to a private copy of the latest pool and publishes it as the next version, which is returned.
+    pub fn update<F: FnOnce(&mut P)>(&self, f: F) -> u64 {
+        let _guard = self.writer.lock().unwrap();
+        let latest = self.current.load();
+        let mut pool = latest.pool.clone();
+        f(&mut pool);
+        let version = latest.version + 1;
+        self.current.store(Arc::new(PoolState { version, pool }));
+        if let Some((pool_id, bus)) = &self.bus {
+            bus.publish(PoolUpdated {
+                pool_id: *pool_id,
+                version,
+            });
+        }
+        version
+    }
+}
+
+pub trait Versioned {
+    fn version(&self) -> u64;
+}
+
+impl<P: Clone> Versioned for SharedPool<P> {
+    fn version(&self) -> u64 {
+        self.current.load().version
+    }
+}
+
+// [CancellationToken] records the version of every pool a search read. The search is stale once any of those pools publishes a newer version, or when cancel is called.
+#[derive(Default)]
+pub struct CancellationToken<'a> {
+    reads: Vec<(&'a (dyn Versioned + Sync), u64)>,
+    cancelled: AtomicBool,
+}
+
+impl<'a> CancellationToken<'a> {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn read<P: Clone + Send + Sync>(&mut self, shared: &'a SharedPool<P>) -> Arc<PoolState<P>> {
+        let snapshot = shared.snapshot();
+        self.reads.push((shared, snapshot.version));
+        snapshot
+    }
+
+    pub fn versions(&self) -> Vec<u64> {
+        self.reads.iter().map(|(_, version)| *version).collect()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.reads
+            .iter()
+            .any(|(shared, version)| shared.version() > *version)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.is_stale()
+    }
+}
+
+// A stale outcome was cut short or finished on state that has since changed, and should be discarded.
+#[derive(Copy, Clone, Debug)]
+pub struct SearchOutcome {
+    pub amount_in: f64,
+    pub stale: bool,
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::token::Token;
+    use crate::v2::{add, Pool};
+    use std::thread;
+
+    fn pool() -> Pool {
+        Pool::new(Token::Eth, Token::Dai, 4., 4000.)
+    }
+
+    #[test]
+    fn snapshot_is_isolated_from_updates() {
+        let shared = SharedPool::new(pool());
+        let before = shared.snapshot();
+
+        let version = shared.update(|p| add(p, 1., 1000.));
+
+        assert_eq!(version, 1);
+        assert_eq!(shared.version(), 1);
+        assert_eq!(before.version, 0);
+        assert_eq!(*before.pool.x.read().unwrap(), 4.);
+        assert_eq!(*shared.snapshot().pool.x.read().unwrap(), 5.);
+    }
+
+    #[test]
+    fn writer_does_not_wait_for_held_snapshot() {
+        let shared = Arc::new(SharedPool::new(pool()));
+        let held = shared.snapshot();
+
+        let writer = Arc::clone(&shared);
+        thread::spawn(move || {
+            for _ in 0..10 {
+                writer.update(|p| add(p, 1., 0.));
+            }
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(*held.pool.x.read().unwrap(), 4.);
+        assert_eq!(shared.version(), 10);
+        assert_eq!(*shared.snapshot().pool.x.read().unwrap(), 14.);
+    }
+
+    #[test]
+    fn token_goes_stale_when_a_read_pool_advances() {
+        let first = SharedPool::new(pool());
+        let second = SharedPool::new(pool());
+        second.update(|p| add(p, 1., 0.));
+
+        let mut cancel = CancellationToken::new();
+        cancel.read(&first);
+        cancel.read(&second);
+
+        assert_eq!(cancel.versions(), vec![0, 1]);
+        assert!(!cancel.is_cancelled());
+
+        second.update(|p| add(p, 1., 0.));
+
+        assert!(cancel.is_stale());
+        assert!(cancel.is_cancelled());
+    }
+
+    #[test]
+    fn update_publishes_on_bus() {
+        let bus = Arc::new(UpdateBus::new());
+        let receiver = bus.subscribe();
+        let shared = SharedPool::with_bus(pool(), 7, Arc::clone(&bus));
+
+        shared.update(|p| add(p, 1., 0.));
+        shared.update(|p| add(p, 1., 0.));
+
+        let events: Vec<PoolUpdated> = receiver.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                PoolUpdated {
+                    pool_id: 7,
+                    version: 1
+                },
+                PoolUpdated {
+                    pool_id: 7,
+                    version: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn explicit_cancel() {
+        let cancel = CancellationToken::new();
+
+        cancel.cancel();
+
+        assert!(!cancel.is_stale());
+        assert!(cancel.is_cancelled());
+    }
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Token {
+  Eth,
+  Dai,
+}
+
+impl Token {
+  pub fn other(self) -> Token {
+    match self {
+      Token::Eth => Token::Dai,
+      Token::Dai => Token::Eth,
+    }
+  }
+
+  // [mainnet_address] is the token's Ethereum mainnet contract, WETH standing in for Eth.
+  pub fn mainnet_address(self) -> &'static str {
+    match self {
+      Token::Eth => "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+      Token::Dai => "0x6B175474E89094C44Da98b954EedeAC495271d0F",
+    }
+  }
+}
+
+impl FromStr for Token {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "eth" => Ok(Token::Eth),
+      "dai" => Ok(Token::Dai),
+      _ => Err(format!("unknown token {:?}", s)),
+    }
+  }
+}
+
+// [TokenBehavior] is how a token's transfers differ from exact amounts. transfer_tax_bps is kept back from every transfer. rebase_multiplier scales balances between a quote and its settlement, so 1.0 is a token that does not rebase.
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
+pub struct TokenBehavior {
+  pub transfer_tax_bps: u32,
+  pub rebase_multiplier: f64,
+}
+
+impl Default for TokenBehavior {
+  fn default() -> Self {
+    TokenBehavior {
+      transfer_tax_bps: 0,
+      rebase_multiplier: 1.,
+    }
+  }
+}
+
+impl TokenBehavior {
+  // [taxed] is what arrives when amount is sent.
+  pub fn taxed(&self, amount: f64) -> f64 {
+    amount * (1. - self.transfer_tax_bps as f64 / 10000.)
+  }
+
+  // [rebased] is a balance of amount after the token rebases.
+  pub fn rebased(&self, amount: f64) -> f64 {
+    amount * self.rebase_multiplier
+  }
+
+  pub fn is_exact(&self) -> bool {
+    *self == TokenBehavior::default()
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TokenInfo {
+  pub symbol: String,
+  pub address: String,
+  pub token: Token,
+  #[serde(default)]
+  pub behavior: TokenBehavior,
+}
+
+// [TokenRegistry] maps the symbols and addresses used in data files onto the tokens the model knows about. It is read from a JSON array of TokenInfo.
+#[derive(Default, Clone, Debug)]
+pub struct TokenRegistry {
+  tokens: Vec<TokenInfo>,
+}
+
+impl TokenRegistry {
+  pub fn new(tokens: Vec<TokenInfo>) -> Self {
+    TokenRegistry { tokens }
+  }
+
+  pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+    let tokens: Vec<TokenInfo> = serde_json::from_slice(&fs::read(path)?)?;
+    Ok(TokenRegistry::new(tokens))
+  }
+
+  // [resolve] matches name against registered symbols and addresses, ignoring case, and falls back to the built-in token names.
+  pub fn resolve(&self, name: &str) -> Result<Token, String> {
+    self
+      .tokens
+      .iter()
+      .find(|t| t.symbol.eq_ignore_ascii_case(name) || t.address.eq_ignore_ascii_case(name))
+      .map(|t| t.token)
+      .map_or_else(|| name.parse(), Ok)
+  }
+
+  // [address] is the address of the first entry registered for token.
+  pub fn address(&self, token: Token) -> Option<String> {
+    self.tokens.iter().find(|t| t.token == token).map(|t| t.address.clone())
+  }
+
+  // [behavior] is the transfer behaviour of the first entry registered for token, or exact transfers if there is none.
+  pub fn behavior(&self, token: Token) -> TokenBehavior {
+    self
+      .tokens
+      .iter()
+      .find(|t| t.token == token)
+      .map(|t| t.behavior)
+      .unwrap_or_default()
+  }
+}
@@ -0,0 +1,705 @@
+use crate::flash::FlashError;
+use crate::gas::GasModel;
+use crate::math;
+use crate::opportunity::{Opportunity, OpportunityConfig, Venue};
+use crate::state::{CancellationToken, SearchOutcome};
+use crate::token::Token;
+use std::sync::RwLock;
+
+pub struct Pool {
+  pub token_x: Token,
+  pub token_y: Token,
+  pub x: RwLock<f64>,
+  pub y: RwLock<f64>,
+  pub k: RwLock<f64>,
+  pub block_timestamp: RwLock<u64>,
+  pub block_timestamp_last: RwLock<u64>,
+  pub price_0_cumulative_last: RwLock<f64>,
+  pub price_1_cumulative_last: RwLock<f64>,
+  pub protocol_fee: RwLock<f64>,
+  pub k_last: RwLock<f64>,
+  pub protocol_fees: RwLock<(f64, f64)>,
+}
+
+impl Clone for Pool {
+  fn clone(&self) -> Self {
+    Pool {
+      token_x: self.token_x,
+      token_y: self.token_y,
+      x: RwLock::new(*self.x.read().unwrap()),
+      y: RwLock::new(*self.y.read().unwrap()),
+      k: RwLock::new(*self.k.read().unwrap()),
+      block_timestamp: RwLock::new(*self.block_timestamp.read().unwrap()),
+      block_timestamp_last: RwLock::new(*self.block_timestamp_last.read().unwrap()),
+      price_0_cumulative_last: RwLock::new(*self.price_0_cumulative_last.read().unwrap()),
+      price_1_cumulative_last: RwLock::new(*self.price_1_cumulative_last.read().unwrap()),
+      protocol_fee: RwLock::new(*self.protocol_fee.read().unwrap()),
+      k_last: RwLock::new(*self.k_last.read().unwrap()),
+      protocol_fees: RwLock::new(*self.protocol_fees.read().unwrap()),
+    }
+  }
+}
+
+impl Pool {
+  pub fn new(token_x: Token, token_y: Token, x: f64, y: f64) -> Self {
+    Pool {
+      token_x,
+      token_y,
+      x: RwLock::new(x),
+      y: RwLock::new(y),
+      k: RwLock::new(x + y),
+      block_timestamp: RwLock::new(0),
+      block_timestamp_last: RwLock::new(0),
+      price_0_cumulative_last: RwLock::new(0.),
+      price_1_cumulative_last: RwLock::new(0.),
+      protocol_fee: RwLock::new(0.),
+      k_last: RwLock::new(0.),
+      protocol_fees: RwLock::new((0., 0.)),
+    }
+  }
+
+  // [set_protocol_fee] turns the fee switch on with share of LP fees going to the protocol, 1/6 in Uniswap V2, or off with zero.
+  pub fn set_protocol_fee(&self, share: f64) {
+    *self.protocol_fee.write().unwrap() = share;
+  }
+
+  // [set_block_timestamp] moves the pool's simulated clock, which only ever runs forward.
+  pub fn set_block_timestamp(&self, timestamp: u64) {
+    let mut now = self.block_timestamp.write().unwrap();
+    *now = timestamp.max(*now);
+  }
+
+  // [accumulate] returns the cumulative prices as they would be at now, charging the current reserves for the time since block_timestamp_last. A now earlier than block_timestamp_last charges nothing.
+  fn accumulate(&self, now: u64) -> (f64, f64) {
+    let x = *self.x.read().unwrap();
+    let y = *self.y.read().unwrap();
+    let elapsed = now.saturating_sub(*self.block_timestamp_last.read().unwrap());
+    let mut price_0 = *self.price_0_cumulative_last.read().unwrap();
+    let mut price_1 = *self.price_1_cumulative_last.read().unwrap();
+    if elapsed > 0 && x > 0. && y > 0. {
+      price_0 += y / x * math::get_q112() * elapsed as f64;
+      price_1 += x / y * math::get_q112() * elapsed as f64;
+    }
+    (price_0, price_1)
+  }
+}
+
+// [update_cumulatives] runs before every reserve change, like UniswapV2Pair._update: the reserves being replaced are charged for the time they held.
+fn update_cumulatives(pool: &Pool) {
+  let now = *pool.block_timestamp.read().unwrap();
+  let (price_0, price_1) = pool.accumulate(now);
+  *pool.price_0_cumulative_last.write().unwrap() = price_0;
+  *pool.price_1_cumulative_last.write().unwrap() = price_1;
+  let mut last = pool.block_timestamp_last.write().unwrap();
+  *last = now.max(*last);
+}
+
+// [current_cumulative_prices] is what price0CumulativeLast and price1CumulativeLast would read if the pair synced now, as UQ112x112 values held in f64, plus the pool's current time.
+pub fn current_cumulative_prices(pool: &Pool) -> (f64, f64, u64) {
+  let now = *pool.block_timestamp.read().unwrap();
+  let (price_0, price_1) = pool.accumulate(now);
+  (price_0, price_1, now)
+}
+
+// [twap] is the time weighted average price between two readings of one cumulative price, decoded from UQ112x112. It is None unless end was read after start.
+pub fn twap(start: (f64, u64), end: (f64, u64)) -> Option<f64> {
+  let elapsed = end.1.checked_sub(start.1).filter(|elapsed| *elapsed > 0)?;
+  Some((end.0 - start.0) / elapsed as f64 / math::get_q112())
+}
+
+pub struct Trader {
+  pub amt_eth: RwLock<f64>,
+  pub amt_dai: RwLock<f64>,
+}
+
+pub fn add(pool: &Pool, add_to_x: f64, add_to_y: f64) {
+  update_cumulatives(pool);
+  *pool.x.write().unwrap() += add_to_x;
+  *pool.y.write().unwrap() += add_to_y;
+  *pool.k.write().unwrap() = *pool.x.read().unwrap() + *pool.y.read().unwrap();
+}
+
+pub fn remove(pool: &Pool, rem_from_x: f64, rem_from_y: f64) {
+  update_cumulatives(pool);
+  *pool.x.write().unwrap() -= rem_from_x;
+  *pool.y.write().unwrap() -= rem_from_y;
+  *pool.k.write().unwrap() = *pool.x.read().unwrap() + *pool.y.read().unwrap();
+}
+
+// [mint_fee] follows UniswapV2Pair._mintFee. With the switch on, the protocol is minted liquidity worth share * (1 - sqrt(k_last) / sqrt(k)) of the pool, which is share of the fees earned since k_last; that is (sqrt(k) - sqrt(k_last)) / (6 sqrt(k)) at 1/6. The reserves still hold those tokens, so protocol_fees records what the minted liquidity was worth when minted.
+fn mint_fee(pool: &Pool) -> bool {
+  let share = *pool.protocol_fee.read().unwrap();
+  let k_last = *pool.k_last.read().unwrap();
+  if share <= 0. {
+    if k_last != 0. {
+      *pool.k_last.write().unwrap() = 0.;
+    }
+    return false;
+  }
+  if k_last != 0. {
+    let x = *pool.x.read().unwrap();
+    let y = *pool.y.read().unwrap();
+    let root_k = (x * y).sqrt();
+    let root_k_last = k_last.sqrt();
+    if root_k > root_k_last {
+      let minted = share * (1. - root_k_last / root_k);
+      let mut fees = pool.protocol_fees.write().unwrap();
+      fees.0 += minted * x;
+      fees.1 += minted * y;
+    }
+  }
+  true
+}
+
+// [add_liquidity] is a mint: the protocol's fee is minted on the reserves before the deposit, and k_last is reset after it.
+pub fn add_liquidity(pool: &Pool, add_to_x: f64, add_to_y: f64) {
+  let fee_on = mint_fee(pool);
+  add(pool, add_to_x, add_to_y);
+  if fee_on {
+    *pool.k_last.write().unwrap() = *pool.x.read().unwrap() * *pool.y.read().unwrap();
+  }
+}
+
+// [remove_liquidity] is a burn, settling the protocol's fee the same way as [add_liquidity].
+pub fn remove_liquidity(pool: &Pool, rem_from_x: f64, rem_from_y: f64) {
+  let fee_on = mint_fee(pool);
+  remove(pool, rem_from_x, rem_from_y);
+  if fee_on {
+    *pool.k_last.write().unwrap() = *pool.x.read().unwrap() * *pool.y.read().unwrap();
+  }
+}
+
+pub fn get_amount_out(amount_in: f64, pool: &Pool, token_in: Token, fee: f64) -> f64 {
+  let amount_in_less_fee = amount_in * (1. - fee);
+  let py = *pool.y.read().unwrap();
+  let px = *pool.x.read().unwrap();
+  if token_in == pool.token_x {
+    let price = py / px;
+    let amount_out = amount_in_less_fee * price;
+    if amount_out <= py {
+      remove(pool, 0., amount_out);
+      add(pool, amount_in, 0.);
+      amount_out
+    } else {
+      0.
+    }
+  } else {
+    let price = px / py;
+    let amount_out = amount_in_less_fee * price;
+    if amount_out <= px {
+      remove(pool, amount_out, 0.);
+      add(pool, 0., amount_in);
+      amount_out
+    } else {
+      0.
+    }
+  }
+}
+
+// [quote_amount_out] prices a swap with y = (F*Y*x) / (X + F*x) against the current reserves without changing them.
+pub fn quote_amount_out(amount_in: f64, pool: &Pool, token_in: Token, fee: f64) -> f64 {
+  let (reserve_in, reserve_out) = if token_in == pool.token_x {
+    (*pool.x.read().unwrap(), *pool.y.read().unwrap())
+  } else {
+    (*pool.y.read().unwrap(), *pool.x.read().unwrap())
+  };
+  let amount_in_less_fee = amount_in * (1. - fee);
+  (reserve_out * amount_in_less_fee) / (reserve_in + amount_in_less_fee)
+}
+
+// [swap_amount_out] executes the swap [quote_amount_out] prices: amount_in joins its reserve, fee included, and the output leaves the other one.
+pub fn swap_amount_out(amount_in: f64, pool: &Pool, token_in: Token, fee: f64) -> f64 {
+  let amount_out = quote_amount_out(amount_in, pool, token_in, fee);
+  if token_in == pool.token_x {
+    remove(pool, 0., amount_out);
+    add(pool, amount_in, 0.);
+  } else {
+    remove(pool, amount_out, 0.);
+    add(pool, 0., amount_in);
+  }
+  amount_out
+}
+
+// Relative slack allowed in the flash swap invariant check, so a repayment computed by [flash_repayment] is not rejected for rounding.
+const FLASH_TOLERANCE: f64 = 1e-12;
+
+// [flash_swap] sends amount_x_out and amount_y_out before anything is paid, like UniswapV2Pair.swap with calldata. callback receives the amounts and returns what it repays of each token. The pool then requires (x' - fee*x_in)(y' - fee*y_in) >= x*y on its new balances, the constant product of [quote_amount_out] rather than the linear model of [get_amount_out], and keeps its old reserves if the check fails.
+pub fn flash_swap<F>(
+  pool: &Pool,
+  amount_x_out: f64,
+  amount_y_out: f64,
+  fee: f64,
+  callback: F,
+) -> Result<(f64, f64), FlashError>
+where
+  F: FnOnce(f64, f64) -> (f64, f64),
+{
+  for (token, amount) in [(pool.token_x, amount_x_out), (pool.token_y, amount_y_out)] {
+    if amount.is_nan() || amount < 0. {
+      return Err(FlashError::InvalidAmount { token, amount });
+    }
+  }
+  let x = *pool.x.read().unwrap();
+  let y = *pool.y.read().unwrap();
+  if amount_x_out >= x {
+    return Err(FlashError::InsufficientLiquidity {
+      token: pool.token_x,
+      requested: amount_x_out,
+      available: x,
+    });
+  }
+  if amount_y_out >= y {
+    return Err(FlashError::InsufficientLiquidity {
+      token: pool.token_y,
+      requested: amount_y_out,
+      available: y,
+    });
+  }
+
+  let (x_in, y_in) = callback(amount_x_out, amount_y_out);
+
+  let before = x * y;
+  let after = (x - amount_x_out + x_in * (1. - fee)) * (y - amount_y_out + y_in * (1. - fee));
+  if after < before * (1. - FLASH_TOLERANCE) {
+    return Err(FlashError::InvariantBroken { before, after });
+  }
+  remove(pool, amount_x_out, amount_y_out);
+  add(pool, x_in, y_in);
+  Ok((x_in, y_in))
+}
+
+// [flash_repayment] is the least of the other token that settles a flash swap of amount_out of token_out.
+pub fn flash_repayment(pool: &Pool, token_out: Token, amount_out: f64, fee: f64) -> f64 {
+  let (reserve_in, reserve_out) = if token_out == pool.token_x {
+    (*pool.y.read().unwrap(), *pool.x.read().unwrap())
+  } else {
+    (*pool.x.read().unwrap(), *pool.y.read().unwrap())
+  };
+  reserve_in * amount_out / ((reserve_out - amount_out) * (1. - fee))
+}
+
+// [flash_arb_profit] runs the arbitrage of amt_in with no starting inventory. It flash swaps what amt_in of token_in would buy out of pool1, sells that into pool2, and repays pool1 in token_in from the proceeds. The pools are copied, so neither changes. An arbitrage that cannot repay fails with the pool's error instead of going negative.
+pub fn flash_arb_profit(
+  pool1: &Pool,
+  pool2: &Pool,
+  token_in: Token,
+  fee: f64,
+  amt_in: f64,
+) -> Result<f64, FlashError> {
+  let lender = pool1.clone();
+  let mid = quote_amount_out(amt_in, &lender, token_in, fee);
+  let out = quote_amount_out(mid, pool2, token_in.other(), fee);
+  let repaid = out.min(amt_in);
+  let borrow_x = token_in != lender.token_x;
+  let (x_out, y_out) = if borrow_x { (mid, 0.) } else { (0., mid) };
+  flash_swap(&lender, x_out, y_out, fee, |_, _| if borrow_x { (0., repaid) } else { (repaid, 0.) })?;
+  Ok(out - repaid)
+}
+
+pub fn swap(trader: &mut Trader, pool: &Pool, token_in: Token, amount_in: f64, fee: f64) {
+  let amt_eth = *trader.amt_eth.read().unwrap();
+  let amt_dai = *trader.amt_dai.read().unwrap();
+  if token_in == Token::Eth && amt_eth > amount_in {
+    let amt_out = get_amount_out(amount_in, pool, token_in, fee);
+    if amt_out > 0. {
+      *trader.amt_eth.write().unwrap() = amt_eth - amount_in;
+      *trader.amt_dai.write().unwrap() = amt_dai + amt_out;
+    }
+  } else if token_in == Token::Dai && amt_dai > amount_in {
+    let amt_out = get_amount_out(amount_in, pool, token_in, fee);
+    if amt_out > 0. {
+      *trader.amt_dai.write().unwrap() = amt_dai - amt_out;
+      *trader.amt_eth.write().unwrap() = amt_dai + amount_in;
+    }
+  }
+}
+
+// [calc_two_pool_arb_legs] returns the output of the first swap and the output of the second.
+pub fn calc_two_pool_arb_legs(
+  x_in: f64,
+  xr1: f64,
+  xr2: f64,
+  yr1: f64,
+  yr2: f64,
+  fee: f64,
+) -> (f64, f64) {
+  let s = (fee * xr1 * x_in) / (yr1 + (fee * x_in));
+  let n = fee * yr2 * s;
+  let d = xr2 + fee * s;
+  (s, n / d)
+}
+
+pub fn calc_two_pool_arb_profit(x_in: f64, xr1: f64, xr2: f64, yr1: f64, yr2: f64, fee: f64) -> f64 {
+  calc_two_pool_arb_legs(x_in, xr1, xr2, yr1, yr2, fee).1
+}
+
+pub fn detect_arb_legs(pool1: &Pool, pool2: &Pool, token_in: Token, fee: f64, amt_in: f64) -> (f64, f64) {
+  let is_x_1 = token_in == pool1.token_x;
+  let is_x_2 = token_in == pool2.token_x;
+
+  let x1 = *pool1.x.read().unwrap();
+  let x2 = *pool2.x.read().unwrap();
+  let y1 = *pool1.y.read().unwrap();
+  let y2 = *pool2.y.read().unwrap();
+
+  match (is_x_1, is_x_2) {
+    (true, true) => calc_two_pool_arb_legs(amt_in, x1, x2, y1, y2, fee),
+    (true, false) => calc_two_pool_arb_legs(amt_in, x1, y2, y1, x2, fee),
+    (false, false) => calc_two_pool_arb_legs(amt_in, y1, y2, x1, x2, fee),
+    (false, true) => calc_two_pool_arb_legs(amt_in, y1, x2, x1, y2, fee),
+  }
+}
+
+pub fn detect_arb(pool1: &Pool, pool2: &Pool, token_in: Token, fee: f64, amt_in: f64) -> f64 {
+  detect_arb_legs(pool1, pool2, token_in, fee, amt_in).1
+}
+
+// [calc_two_pool_arb_gas] returns the gas cost of the two-hop arbitrage in units of token_in. V2 swaps cost the same gas regardless of size.
+pub fn calc_two_pool_arb_gas(token_in: Token, gas: &GasModel, reference: &Pool) -> f64 {
+  gas.cost_in_token(gas.tx_gas(2. * gas.v2_hop_gas()), token_in, reference)
+}
+
+pub fn find_optimal_arb(
+  pool1: &Pool,
+  pool2: &Pool,
+  token_in: Token,
+  fee: f64,
+  max_amt_in: f64,
+  gas: &GasModel,
+  reference: &Pool,
+) -> f64 {
+  find_optimal_arb_until(
+    pool1,
+    pool2,
+    token_in,
+    fee,
+    max_amt_in,
+    gas,
+    reference,
+    &CancellationToken::new(),
+  )
+  .amount_in
+}
+
+// [find_optimal_arb_until] is [find_optimal_arb] that checks cancel before every step and stops early, marking the outcome stale, once it fires. A max_amt_in the steps cannot get past, such as infinity, NaN or a value too large for a step to change, is not searched and gives zero.
+#[allow(clippy::too_many_arguments)]
+pub fn find_optimal_arb_until(
+  pool1: &Pool,
+  pool2: &Pool,
+  token_in: Token,
+  fee: f64,
+  max_amt_in: f64,
+  gas: &GasModel,
+  reference: &Pool,
+  cancel: &CancellationToken,
+) -> SearchOutcome {
+  let step = 0.01;
+  if max_amt_in.is_nan() || max_amt_in + step == max_amt_in {
+    return SearchOutcome {
+      amount_in: 0.,
+      stale: cancel.is_cancelled(),
+    };
+  }
+  let gas_cost = calc_two_pool_arb_gas(token_in, gas, reference);
+  let mut amt = step;
+  let mut max_out = 0.;
+  let mut opt_amt = 0.;
+  while amt <= max_amt_in {
+    if cancel.is_cancelled() {
+      return SearchOutcome {
+        amount_in: opt_amt,
+        stale: true,
+      };
+    }
+    let amt_out = detect_arb(pool1, pool2, token_in, fee, amt) - amt - gas_cost;
+    if amt_out > max_out {
+      max_out = amt_out;
+      opt_amt = amt;
+    }
+    amt += step;
+  }
+  SearchOutcome {
+    amount_in: opt_amt,
+    stale: cancel.is_cancelled(),
+  }
+}
+
+// [two_pool_opportunity] packages the arbitrage of amt_in with its gas and per-leg minimum outputs.
+#[allow(clippy::too_many_arguments)]
+pub fn two_pool_opportunity(
+  pool1: &Pool,
+  pool2: &Pool,
+  token_in: Token,
+  fee: f64,
+  amt_in: f64,
+  gas: &GasModel,
+  reference: &Pool,
+  config: &OpportunityConfig,
+) -> Opportunity {
+  let (mid, out) = detect_arb_legs(pool1, pool2, token_in, fee, amt_in);
+  let gas_cost = calc_two_pool_arb_gas(token_in, gas, reference);
+  Opportunity {
+    token_in,
+    amount_in: amt_in,
+    gross_profit: out - amt_in,
+    gas_cost,
+    net_profit: out - amt_in - gas_cost,
+    legs: vec![
+      config.leg(Venue::V2, token_in, amt_in, mid),
+      config.leg(Venue::V2, token_in.other(), mid, out),
+    ],
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+  use std::sync::Arc;
+
+  #[test]
+  fn initialize() {
+    let xx = 1000.;
+    let yy = 200.;
+    let pool = Pool::new(Token::Eth, Token::Dai, xx, yy);
+    let trader = Trader {
+      amt_eth: RwLock::new(xx),
+      amt_dai: RwLock::new(yy),
+    };
+
+    assert_eq!(*trader.amt_eth.read().unwrap(), 1000.);
+    assert_eq!(*trader.amt_dai.read().unwrap(), 200.);
+
+    assert_eq!(*pool.x.read().unwrap(), 1000.);
+    assert_eq!(*pool.y.read().unwrap(), 200.);
+  }
+
+  #[test]
+  fn add_and_remove() {
+    let xx = 1000.;
+    let yy = 200.;
+    let pool = Arc::new(Pool::new(Token::Eth, Token::Dai, xx, yy));
+
+    let safepool = Arc::clone(&pool);
+
+    add(&safepool, 4., 4.);
+    assert_eq!(*Arc::clone(&pool).x.read().unwrap(), 1004.);
+    assert_eq!(*Arc::clone(&pool).y.read().unwrap(), 204.);
+  }
+
+  #[test]
+  fn cumulative_prices_track_reserves_over_time() {
+    let pool = Pool::new(Token::Eth, Token::Dai, 4., 4000.);
+    pool.set_block_timestamp(100);
+    add(&pool, 0., 0.);
+    let start = current_cumulative_prices(&pool);
+
+    pool.set_block_timestamp(130);
+    add(&pool, 4., 12000.);
+    pool.set_block_timestamp(140);
+    let end = current_cumulative_prices(&pool);
+
+    assert_eq!(*pool.block_timestamp_last.read().unwrap(), 130);
+    assert_eq!(end.2, 140);
+    let average = twap((start.0, start.2), (end.0, end.2)).unwrap();
+    assert!((average - (1000. * 30. + 2000. * 10.) / 40.).abs() < 1e-9);
+    let inverse = twap((start.1, start.2), (end.1, end.2)).unwrap();
+    assert!((inverse - (0.001 * 30. + 0.0005 * 10.) / 40.).abs() < 1e-12);
+  }
+
+  #[test]
+  fn clock_behind_last_update_is_not_charged() {
+    let pool = Pool::new(Token::Eth, Token::Dai, 4., 4000.);
+    pool.set_block_timestamp(100);
+    add(&pool, 0., 0.);
+    let start = current_cumulative_prices(&pool);
+
+    *pool.block_timestamp.write().unwrap() = 40;
+    add(&pool, 4., 4000.);
+    let end = current_cumulative_prices(&pool);
+
+    assert_eq!(*pool.block_timestamp_last.read().unwrap(), 100);
+    assert_eq!((end.0, end.1), (start.0, start.1));
+    assert_eq!(twap((start.0, start.2), (end.0, end.2)), None);
+    assert_eq!(twap((start.0, start.2), (start.0, start.2)), None);
+  }
+
+  #[test]
+  fn test_swap() {
+    let xx = 1000.;
+    let yy = 200.;
+    let pool = Pool::new(Token::Eth, Token::Dai, xx, yy);
+    let mut trader = Trader {
+      amt_eth: RwLock::new(xx),
+      amt_dai: RwLock::new(yy),
+    };
+    swap(&mut trader, &pool, Token::Eth, 1., 0.03);
+
+    assert_eq!(*trader.amt_eth.read().unwrap(), 999.);
+    assert_eq!(*trader.amt_dai.read().unwrap(), 200.194);
+  }
+
+  #[test]
+  fn quote_matches_constant_product() {
+    let pool = Pool::new(Token::Eth, Token::Dai, 4., 20000.);
+
+    let out = quote_amount_out(1., &pool, Token::Eth, 0.);
+
+    assert_eq!(out, 4000.);
+    assert_eq!(*pool.x.read().unwrap(), 4.);
+    assert_eq!(*pool.y.read().unwrap(), 20000.);
+  }
+
+  #[test]
+
+  fn find_optimal_amount() {
+    let pool1 = Arc::new(Pool::new(Token::Eth, Token::Dai, 4., 3500.));
+    let pool2 = Arc::new(Pool::new(Token::Eth, Token::Dai, 4., 4000.));
+
+    let gas = GasModel::default();
+
+    let b1 = find_optimal_arb(
+      &Arc::clone(&pool1),
+      &Arc::clone(&pool2),
+      Token::Eth,
+      0.97,
+      2.,
+      &gas,
+      &pool1,
+    );
+    let b2 = find_optimal_arb(
+      &Arc::clone(&pool2),
+      &Arc::clone(&pool1),
+      Token::Eth,
+      0.97,
+      2.,
+      &gas,
+      &pool1,
+    );
+    assert_eq!(b1, 1.9900000000000015);
+    assert_eq!(
+      detect_arb(
+        &Arc::clone(&pool1),
+        &Arc::clone(&pool2),
+        Token::Eth,
+        0.97,
+        b1
+      ) - b1,
+      0.14755301325556314
+    );
+    assert_eq!(b2, 0.);
+    assert_eq!(
+      detect_arb(
+        &Arc::clone(&pool1),
+        &Arc::clone(&pool2),
+        Token::Eth,
+        0.97,
+        b2
+      ) - b2,
+      0.
+    );
+    for max_amt_in in [f64::INFINITY, f64::NAN, 1e300] {
+      assert_eq!(
+        find_optimal_arb(&pool1, &pool2, Token::Eth, 0.97, max_amt_in, &gas, &pool1),
+        0.
+      );
+    }
+  }
+
+  #[test]
+  fn flash_swap_checks_invariant_after_callback() {
+    let pool = Pool::new(Token::Eth, Token::Dai, 4., 20000.);
+    let owed = flash_repayment(&pool, Token::Dai, 1000., 0.003);
+
+    let short = flash_swap(&pool, 0., 1000., 0.003, |_, y_out| {
+      assert_eq!(y_out, 1000.);
+      (owed * 0.99, 0.)
+    });
+    assert!(matches!(short, Err(FlashError::InvariantBroken { .. })));
+    assert_eq!(*pool.y.read().unwrap(), 20000.);
+
+    let paid = flash_swap(&pool, 0., 1000., 0.003, |_, _| (owed, 0.)).unwrap();
+    assert_eq!(paid, (owed, 0.));
+    assert_eq!(*pool.x.read().unwrap(), 4. + owed);
+    assert_eq!(*pool.y.read().unwrap(), 19000.);
+    assert!(matches!(
+      flash_swap(&pool, 0., 19000., 0.003, |_, _| (1e9, 0.)),
+      Err(FlashError::InsufficientLiquidity { token: Token::Dai, .. })
+    ));
+  }
+
+  #[test]
+  fn flash_arb_needs_no_inventory() {
+    let pool1 = Pool::new(Token::Eth, Token::Dai, 4., 3500.);
+    let pool2 = Pool::new(Token::Eth, Token::Dai, 4., 4000.);
+
+    let profit = flash_arb_profit(&pool1, &pool2, Token::Dai, 0.003, 100.).unwrap();
+    let mid = quote_amount_out(100., &pool1, Token::Dai, 0.003);
+    let out = quote_amount_out(mid, &pool2, Token::Eth, 0.003);
+    assert!(profit > 0.);
+    assert!((profit - (out - 100.)).abs() < 1e-9);
+    assert_eq!(*pool1.y.read().unwrap(), 3500.);
+
+    assert!(flash_arb_profit(&pool2, &pool1, Token::Dai, 0.003, 100.).is_err());
+  }
+
+  #[test]
+  fn fee_switch_mints_a_sixth_of_fee_growth() {
+    let pool = Pool::new(Token::Eth, Token::Dai, 4., 4000.);
+    add_liquidity(&pool, 1., 1000.);
+    assert_eq!(*pool.k_last.read().unwrap(), 0.);
+
+    pool.set_protocol_fee(1. / 6.);
+    add_liquidity(&pool, 0., 0.);
+    assert_eq!(*pool.k_last.read().unwrap(), 5. * 5000.);
+    flash_swap(&pool, 0., 1000., 0.003, |_, _| (2., 0.)).unwrap();
+    let root_k = (7f64 * 4000.).sqrt();
+    remove_liquidity(&pool, 0., 0.);
+
+    let minted = (root_k - 25000f64.sqrt()) / (6. * root_k);
+    let (fees_x, fees_y) = *pool.protocol_fees.read().unwrap();
+    assert!((fees_x - minted * 7.).abs() < 1e-12);
+    assert!((fees_y - minted * 4000.).abs() < 1e-9);
+    assert_eq!(*pool.k_last.read().unwrap(), 7. * 4000.);
+
+    pool.set_protocol_fee(0.);
+    add_liquidity(&pool, 1., 1000.);
+    assert_eq!(*pool.k_last.read().unwrap(), 0.);
+    assert_eq!(pool.protocol_fees.read().unwrap().0, fees_x);
+  }
+
+  #[test]
+  fn gas_swallows_small_opportunity() {
+    let pool1 = Pool::new(Token::Eth, Token::Dai, 4., 3990.);
+    let pool2 = Pool::new(Token::Eth, Token::Dai, 4., 4000.);
+    let free = GasModel {
+      base_fee_gwei: 0.,
+      priority_fee_gwei: 0.,
+      ..GasModel::default()
+    };
+    let expensive = GasModel {
+      base_fee_gwei: 500.,
+      ..GasModel::default()
+    };
+
+    assert!(find_optimal_arb(&pool1, &pool2, Token::Eth, 1., 2., &free, &pool1) > 0.);
+    assert_eq!(find_optimal_arb(&pool1, &pool2, Token::Eth, 1., 2., &expensive, &pool1), 0.);
+  }
+
+  #[test]
+  fn opportunity_matches_detect_arb() {
+    let pool1 = Pool::new(Token::Eth, Token::Dai, 4., 3500.);
+    let pool2 = Pool::new(Token::Eth, Token::Dai, 4., 4000.);
+    let gas = GasModel::default();
+    let config = OpportunityConfig::default();
+
+    let opportunity = two_pool_opportunity(&pool1, &pool2, Token::Eth, 0.97, 1.99, &gas, &pool1, &config);
+
+    assert_eq!(
+      opportunity.gross_profit,
+      detect_arb(&pool1, &pool2, Token::Eth, 0.97, 1.99) - 1.99
+    );
+    assert_eq!(opportunity.legs[1].expected_out, opportunity.gross_profit + 1.99);
+    assert!(config.accepts(&opportunity));
+  }
+}
@@ -0,0 +1,283 @@
+use crate::logs::{group_by_block, LogError, RawLog};
+use crate::v2::{add, Pool};
+
+pub const SYNC_TOPIC: &str = "0x1c411e9a96e071241c2f21f7726b17ae89e3cab4c78be50e062b03a9fffbbad1";
+pub const SWAP_TOPIC: &str = "0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822";
+pub const MINT_TOPIC: &str = "0x4c209b5fc8ad50758f13e2e1088ba56a560dff690a1c6fef26394f4c03821c4f";
+pub const BURN_TOPIC: &str = "0xdccd412f0b1252819cb1fd330b93224ca42612892bb3f4f789976e6d81936496";
+
+// Amounts are raw token units, with token0 as the pool's token_x.
+#[derive(PartialEq, Clone, Debug)]
+pub enum V2Event {
+    Sync {
+        reserve0: f64,
+        reserve1: f64,
+    },
+    Swap {
+        amount0_in: f64,
+        amount1_in: f64,
+        amount0_out: f64,
+        amount1_out: f64,
+    },
+    Mint {
+        amount0: f64,
+        amount1: f64,
+    },
+    Burn {
+        amount0: f64,
+        amount1: f64,
+    },
+}
+
+// [decode_v2_log] returns None for logs that are not one of the pair events we model.
+pub fn decode_v2_log(log: &RawLog) -> Result<Option<V2Event>, LogError> {
+    let topic0 = match log.topics.first() {
+        Some(topic) => topic.to_ascii_lowercase(),
+        None => return Ok(None),
+    };
+    let event = match topic0.as_str() {
+        SYNC_TOPIC => {
+            let data = log.words(2)?;
+            V2Event::Sync {
+                reserve0: data[0].as_u256(),
+                reserve1: data[1].as_u256(),
+            }
+        }
+        SWAP_TOPIC => {
+            let data = log.words(4)?;
+            V2Event::Swap {
+                amount0_in: data[0].as_u256(),
+                amount1_in: data[1].as_u256(),
+                amount0_out: data[2].as_u256(),
+                amount1_out: data[3].as_u256(),
+            }
+        }
+        MINT_TOPIC => {
+            let data = log.words(2)?;
+            V2Event::Mint {
+                amount0: data[0].as_u256(),
+                amount1: data[1].as_u256(),
+            }
+        }
+        BURN_TOPIC => {
+            let data = log.words(2)?;
+            V2Event::Burn {
+                amount0: data[0].as_u256(),
+                amount1: data[1].as_u256(),
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(event))
+}
+
+// [PendingSync] is the last Sync seen in a log stream, with the reserves the model held before it.
+struct PendingSync {
+    block_number: u64,
+    log_index: u64,
+    before: (f64, f64),
+}
+
+// [reserve_deltas] is how far a Swap, Mint or Burn moves the reserves, as (token_x, token_y). Sync moves nothing by itself.
+fn reserve_deltas(event: &V2Event) -> (f64, f64) {
+    match event {
+        V2Event::Sync { .. } => (0., 0.),
+        V2Event::Swap {
+            amount0_in,
+            amount1_in,
+            amount0_out,
+            amount1_out,
+        } => (amount0_in - amount0_out, amount1_in - amount1_out),
+        V2Event::Mint { amount0, amount1 } => (*amount0, *amount1),
+        V2Event::Burn { amount0, amount1 } => (-amount0, -amount1),
+    }
+}
+
+// [V2LogReplayer] applies one pair's events to a V2 Pool. UniswapV2Pair._update emits Sync with the resulting reserves just before the Swap, Mint or Burn of the same call, so Sync sets the reserves and the deltas of the event right after it are not applied again. They are only used to measure drift.
+pub struct V2LogReplayer {
+    address: String,
+    drift: (f64, f64),
+    pending: Option<PendingSync>,
+}
+
+impl V2LogReplayer {
+    pub fn new(address: &str) -> Self {
+        V2LogReplayer {
+            address: address.to_string(),
+            drift: (0., 0.),
+            pending: None,
+        }
+    }
+
+    // [drift] is the reserve correction made by the last Sync, as (token_x, token_y): what it reported less what the model computes from the reserves before it and the event it announced.
+    pub fn drift(&self) -> (f64, f64) {
+        self.drift
+    }
+
+    // [apply] applies a single event on its own: Sync overwrites the reserves and the others move them by their amounts.
+    pub fn apply(&mut self, pool: &Pool, event: &V2Event) {
+        match event {
+            V2Event::Sync { reserve0, reserve1 } => {
+                let x = *pool.x.read().unwrap();
+                let y = *pool.y.read().unwrap();
+                self.drift = (reserve0 - x, reserve1 - y);
+                add(pool, reserve0 - x, reserve1 - y);
+            }
+            _ => {
+                let (delta_x, delta_y) = reserve_deltas(event);
+                add(pool, delta_x, delta_y);
+            }
+        }
+    }
+
+    // [apply_log] returns whether the log was from this pair and decoded to an event we model. A Swap, Mint or Burn at the log index after a Sync is the call that Sync announced, so its reserves are already in place.
+    pub fn apply_log(&mut self, pool: &Pool, log: &RawLog) -> Result<bool, LogError> {
+        if !log.is_from(&self.address) {
+            return Ok(false);
+        }
+        let event = match decode_v2_log(log)? {
+            Some(event) => event,
+            None => return Ok(false),
+        };
+        let pending = self.pending.take();
+        match (&event, pending) {
+            (V2Event::Sync { .. }, _) => {
+                let before = (*pool.x.read().unwrap(), *pool.y.read().unwrap());
+                self.apply(pool, &event);
+                self.pending = Some(PendingSync {
+                    block_number: log.block_number,
+                    log_index: log.log_index,
+                    before,
+                });
+            }
+            (_, Some(sync))
+                if sync.block_number == log.block_number && sync.log_index + 1 == log.log_index =>
+            {
+                let (delta_x, delta_y) = reserve_deltas(&event);
+                self.drift = (
+                    *pool.x.read().unwrap() - (sync.before.0 + delta_x),
+                    *pool.y.read().unwrap() - (sync.before.1 + delta_y),
+                );
+            }
+            _ => self.apply(pool, &event),
+        }
+        Ok(true)
+    }
+
+    // [replay] applies ordered logs and calls on_block with the pool state at the end of each block. Returns the number of events applied.
+    pub fn replay<F: FnMut(u64, &Pool)>(
+        &mut self,
+        pool: &Pool,
+        logs: &[RawLog],
+        mut on_block: F,
+    ) -> Result<usize, LogError> {
+        let mut applied = 0;
+        for (block_number, block) in group_by_block(logs) {
+            for log in block {
+                if self.apply_log(pool, log)? {
+                    applied += 1;
+                }
+            }
+            on_block(block_number, pool);
+        }
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::token::Token;
+
+    const PAIR: &str = "0xa478c2975ab1ea89e8196811f51a7b7ade33eb11";
+
+    fn log(block: u64, index: u64, topic: &str, data: &[u128]) -> RawLog {
+        RawLog {
+            address: PAIR.to_string(),
+            topics: vec![topic.to_string()],
+            data: format!(
+                "0x{}",
+                data.iter()
+                    .map(|v| format!("{:064x}", v))
+                    .collect::<String>()
+            ),
+            block_number: block,
+            log_index: index,
+            removed: false,
+        }
+    }
+
+    fn empty_pool() -> Pool {
+        Pool::new(Token::Eth, Token::Dai, 0., 0.)
+    }
+
+    #[test]
+    fn replays_pair_block_by_block() {
+        let logs = vec![
+            log(5, 0, SYNC_TOPIC, &[1000, 2000000]),
+            log(5, 1, MINT_TOPIC, &[1000, 2000000]),
+            log(6, 0, SYNC_TOPIC, &[1100, 1819000]),
+            log(6, 1, SWAP_TOPIC, &[100, 0, 0, 181000]),
+            log(7, 0, SYNC_TOPIC, &[990, 1637100]),
+            log(7, 1, BURN_TOPIC, &[110, 181900]),
+        ];
+        let pool = empty_pool();
+        let mut replayer = V2LogReplayer::new(PAIR);
+        let mut blocks = vec![];
+
+        let applied = replayer
+            .replay(&pool, &logs, |block, pool| {
+                blocks.push((block, *pool.x.read().unwrap()))
+            })
+            .unwrap();
+
+        assert_eq!(applied, 6);
+        assert_eq!(blocks, vec![(5, 1000.), (6, 1100.), (7, 990.)]);
+        assert_eq!(*pool.y.read().unwrap(), 1637100.);
+        assert_eq!(replayer.drift(), (0., 0.));
+    }
+
+    #[test]
+    fn sync_corrects_drift() {
+        let pool = empty_pool();
+        let mut replayer = V2LogReplayer::new(PAIR);
+        replayer.apply(
+            &pool,
+            &V2Event::Mint {
+                amount0: 1000.,
+                amount1: 2000000.,
+            },
+        );
+
+        replayer
+            .apply_log(&pool, &log(9, 0, SYNC_TOPIC, &[1003, 1999000]))
+            .unwrap();
+
+        assert_eq!(replayer.drift(), (3., -1000.));
+        assert_eq!(*pool.x.read().unwrap(), 1003.);
+        assert_eq!(*pool.y.read().unwrap(), 1999000.);
+    }
+
+    #[test]
+    fn sync_is_not_applied_twice() {
+        let pool = Pool::new(Token::Eth, Token::Dai, 1000., 2000000.);
+        let mut replayer = V2LogReplayer::new(PAIR);
+
+        replayer
+            .apply_log(&pool, &log(3, 4, SYNC_TOPIC, &[1100, 1819500]))
+            .unwrap();
+        replayer
+            .apply_log(&pool, &log(3, 5, SWAP_TOPIC, &[100, 0, 0, 181000]))
+            .unwrap();
+
+        assert_eq!(*pool.x.read().unwrap(), 1100.);
+        assert_eq!(*pool.y.read().unwrap(), 1819500.);
+        assert_eq!(replayer.drift(), (0., 500.));
+
+        replayer
+            .apply_log(&pool, &log(4, 0, SWAP_TOPIC, &[0, 1000, 1, 0]))
+            .unwrap();
+        assert_eq!(*pool.x.read().unwrap(), 1099.);
+    }
+}
@@ -0,0 +1,1521 @@
+use crate::flash::FlashError;
+use crate::gas::GasModel;
+use crate::math;
+use crate::opportunity::{Opportunity, OpportunityConfig, Venue};
+use crate::oracle::{Oracle, OracleError};
+use crate::state::{CancellationToken, SearchOutcome};
+use crate::token::Token;
+use crate::v2;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::RwLock;
+
+pub fn price_to_tick(price: f64) -> f64 {
+    price.log(1.001).floor()
+}
+
+pub fn tick_to_price(tick: i32) -> f64 {
+    let base: f64 = 1.001;
+    let num: f64 = base.powi(tick);
+    num.sqrt() * math::get_q96()
+}
+
+pub fn price_to_sqrtp(price: f64) -> f64 {
+    price.sqrt() * math::get_q96()
+}
+
+pub fn liquidity0(amount: f64, pa: f64, pb: f64) -> f64 {
+    let q96 = math::get_q96();
+    if pa > pb {
+        (amount * (pa * pb) / q96) / (pb - pa)
+    } else {
+        (amount * (pb * pa) / q96) / (pa - pb)
+    }
+}
+
+pub fn liquidity1(amount: f64, pa: f64, pb: f64) -> f64 {
+    let q96 = math::get_q96();
+    if pa > pb {
+        amount * q96 / (pb - pa)
+    } else {
+        amount * q96 / (pa - pb)
+    }
+}
+
+pub fn calc_amount0(liq: f64, lower_tick: f64, upper_tick: f64) -> f64 {
+    let q96 = math::get_q96();
+    if upper_tick > lower_tick {
+        liq * q96 * (upper_tick - lower_tick) / lower_tick / upper_tick
+    } else {
+        liq * q96 * (lower_tick - upper_tick) / upper_tick / lower_tick
+    }
+}
+
+pub fn calc_amount1(liq: f64, lower_tick: f64, upper_tick: f64) -> f64 {
+    let q96 = math::get_q96();
+    if upper_tick > lower_tick {
+        liq * (upper_tick - lower_tick) / q96
+    } else {
+        liq * (lower_tick - upper_tick) / q96
+    }
+}
+
+pub fn calc_price_diff(amount_in: f64, liquidity: f64) -> f64 {
+    (amount_in * math::get_q96()) / liquidity
+}
+
+// [sqrt_price_to_tick] is the greatest tick whose [tick_to_price] is at or below sqrt_price_x96, in the model's base 1.001.
+pub fn sqrt_price_to_tick(sqrt_price_x96: f64) -> i32 {
+    let price = (sqrt_price_x96 / math::get_q96()).powi(2);
+    let mut tick = price_to_tick(price) as i32;
+    if tick_to_price(tick + 1) <= sqrt_price_x96 {
+        tick += 1;
+    } else if tick_to_price(tick) > sqrt_price_x96 {
+        tick -= 1;
+    }
+    tick
+}
+
+// [tick_spacing_for_fee] is the spacing Uniswap V3's factory enables for each fee tier, with fees as fractions.
+pub fn tick_spacing_for_fee(fee: f64) -> Option<i32> {
+    match (fee * 1e6).round() as u32 {
+        100 => Some(1),
+        500 => Some(10),
+        3000 => Some(60),
+        10000 => Some(200),
+        _ => None,
+    }
+}
+
+pub fn get_next_sqrt_price_from_input(
+    sqrt_price_current_x96: f64,
+    liquidity: f64,
+    amount_remaining: f64,
+    zero_for_one: bool,
+) -> f64 {
+    let q96 = math::get_q96();
+    if zero_for_one {
+        (liquidity * q96 * sqrt_price_current_x96)
+            / (liquidity * q96 + amount_remaining * sqrt_price_current_x96)
+    } else {
+        sqrt_price_current_x96 + (amount_remaining * q96) / liquidity
+    }
+}
+
+pub fn compute_swap_step(
+    sqrt_price_current_x96: f64,
+    sqrt_price_target_x96: f64,
+    liquidity: f64,
+    amount_remaining: f64,
+) -> (f64, f64, f64) {
+    let zero_for_one = sqrt_price_current_x96 >= sqrt_price_target_x96;
+
+    let amount_in_pre_calc = if zero_for_one {
+        calc_amount0(liquidity, sqrt_price_current_x96, sqrt_price_target_x96)
+    } else {
+        calc_amount1(liquidity, sqrt_price_current_x96, sqrt_price_target_x96)
+    };
+
+    let sqrt_price_next_x96 = if amount_remaining >= amount_in_pre_calc {
+        sqrt_price_target_x96
+    } else {
+        get_next_sqrt_price_from_input(
+            sqrt_price_current_x96,
+            liquidity,
+            amount_remaining,
+            zero_for_one,
+        )
+    };
+
+    let amount_in = calc_amount0(liquidity, sqrt_price_current_x96, sqrt_price_next_x96);
+
+    let amount_out = calc_amount1(liquidity, sqrt_price_current_x96, sqrt_price_next_x96);
+
+    if zero_for_one {
+        (sqrt_price_next_x96, amount_in, amount_out)
+    } else {
+        (sqrt_price_next_x96, amount_out, amount_in)
+    }
+}
+
+// liquidity is the gross liquidity referencing the tick. liquidity_net is what crossing it upward adds to the active liquidity: positions add at their lower tick and remove at their upper tick.
+pub struct Tick {
+    pub liquidity: RwLock<f64>,
+    pub liquidity_net: RwLock<f64>,
+    pub initialized: RwLock<bool>,
+}
+
+impl Clone for Tick {
+    fn clone(&self) -> Self {
+        Tick {
+            liquidity: RwLock::new(*self.liquidity.read().unwrap()),
+            liquidity_net: RwLock::new(*self.liquidity_net.read().unwrap()),
+            initialized: RwLock::new(*self.initialized.read().unwrap()),
+        }
+    }
+}
+
+pub struct Position {
+    pub liquidity: RwLock<f64>,
+}
+
+impl Clone for Position {
+    fn clone(&self) -> Self {
+        Position {
+            liquidity: RwLock::new(*self.liquidity.read().unwrap()),
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+pub struct uniswap_v3_pool {
+    pub token_0: Token,
+    pub token_1: Token,
+    pub min_tick: i32,
+    pub max_tick: i32,
+    pub balance_0: RwLock<f64>,
+    pub balance_1: RwLock<f64>,
+    pub tick_mapping: RwLock<HashMap<i32, Tick>>,
+    pub liquidity_mapping: RwLock<HashMap<i32, f64>>,
+    pub position_mapping: RwLock<HashMap<i32, Position>>,
+    pub sqrt_price_x96: RwLock<f64>,
+    pub tick: RwLock<i32>,
+    pub liquidity: RwLock<f64>,
+    pub block_timestamp: RwLock<u64>,
+    pub oracle: RwLock<Oracle>,
+    pub fee_protocol: RwLock<(u8, u8)>,
+    pub protocol_fees: RwLock<(f64, f64)>,
+    pub fee: f64,
+    pub tick_spacing: i32,
+}
+
+// Cloning reads every field under its own lock, so the copy can be swapped against without touching the original.
+impl Clone for uniswap_v3_pool {
+    fn clone(&self) -> Self {
+        uniswap_v3_pool {
+            token_0: self.token_0,
+            token_1: self.token_1,
+            min_tick: self.min_tick,
+            max_tick: self.max_tick,
+            balance_0: RwLock::new(*self.balance_0.read().unwrap()),
+            balance_1: RwLock::new(*self.balance_1.read().unwrap()),
+            tick_mapping: RwLock::new(self.tick_mapping.read().unwrap().clone()),
+            liquidity_mapping: RwLock::new(self.liquidity_mapping.read().unwrap().clone()),
+            position_mapping: RwLock::new(self.position_mapping.read().unwrap().clone()),
+            sqrt_price_x96: RwLock::new(*self.sqrt_price_x96.read().unwrap()),
+            tick: RwLock::new(*self.tick.read().unwrap()),
+            liquidity: RwLock::new(*self.liquidity.read().unwrap()),
+            block_timestamp: RwLock::new(*self.block_timestamp.read().unwrap()),
+            oracle: RwLock::new(self.oracle.read().unwrap().clone()),
+            fee_protocol: RwLock::new(*self.fee_protocol.read().unwrap()),
+            protocol_fees: RwLock::new(*self.protocol_fees.read().unwrap()),
+            fee: self.fee,
+            tick_spacing: self.tick_spacing,
+        }
+    }
+}
+
+impl uniswap_v3_pool {
+    // [new] is an empty pool at the given price, with the tick derived from it as [initialize] does. A zero price leaves the pool uninitialized at tick zero. Its clock starts at zero with a single oracle observation. The fee is left at zero and the tick spacing at one, since callers of new pass a fee to every swap.
+    pub fn new(token_0: Token, token_1: Token, sqrt_price_x96: f64) -> Self {
+        let tick = if sqrt_price_x96 == 0. {
+            0
+        } else {
+            sqrt_price_to_tick(sqrt_price_x96).clamp(math::get_min_tick(), math::get_max_tick() - 1)
+        };
+        uniswap_v3_pool {
+            token_0,
+            token_1,
+            min_tick: math::get_min_tick(),
+            max_tick: math::get_max_tick(),
+            balance_0: RwLock::new(0.),
+            balance_1: RwLock::new(0.),
+            tick_mapping: RwLock::new(HashMap::new()),
+            liquidity_mapping: RwLock::new(HashMap::new()),
+            position_mapping: RwLock::new(HashMap::new()),
+            sqrt_price_x96: RwLock::new(sqrt_price_x96),
+            tick: RwLock::new(tick),
+            liquidity: RwLock::new(0.),
+            block_timestamp: RwLock::new(0),
+            oracle: RwLock::new(Oracle::new(0)),
+            fee_protocol: RwLock::new((0, 0)),
+            protocol_fees: RwLock::new((0., 0.)),
+            fee: 0.,
+            tick_spacing: 1,
+        }
+    }
+
+    // [create] is a pool for one of Uniswap's fee tiers, with the tier's tick spacing, that cannot be swapped against or minted into until it is initialized.
+    pub fn create(token_0: Token, token_1: Token, fee: f64) -> Result<Self, PoolError> {
+        let tick_spacing = tick_spacing_for_fee(fee).ok_or(PoolError::UnsupportedFee(fee))?;
+        Ok(uniswap_v3_pool {
+            fee,
+            tick_spacing,
+            ..uniswap_v3_pool::new(token_0, token_1, 0.)
+        })
+    }
+
+    // [initialize] sets the starting price, like UniswapV3Pool.initialize. The tick is derived from the price, and the oracle restarts at the pool's current time.
+    pub fn initialize(&self, sqrt_price_x96: f64) -> Result<(), PoolError> {
+        if self.is_initialized() {
+            return Err(PoolError::AlreadyInitialized);
+        }
+        if !(math::get_min_sqrt_ratio()..math::get_max_sqrt_ratio()).contains(&sqrt_price_x96) {
+            return Err(PoolError::PriceOutOfBounds(sqrt_price_x96));
+        }
+        let tick = sqrt_price_to_tick(sqrt_price_x96).clamp(self.min_tick, self.max_tick - 1);
+        *self.sqrt_price_x96.write().unwrap() = sqrt_price_x96;
+        *self.tick.write().unwrap() = tick;
+        *self.oracle.write().unwrap() = Oracle::new(*self.block_timestamp.read().unwrap());
+        Ok(())
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        *self.sqrt_price_x96.read().unwrap() != 0.
+    }
+
+    // [set_fee_protocol] takes 1/fee_protocol0 of the fees paid in token_0 and 1/fee_protocol1 of those paid in token_1 for the protocol. As in Uniswap each must be 0 (off) or between 4 and 10.
+    pub fn set_fee_protocol(
+        &self,
+        fee_protocol0: u8,
+        fee_protocol1: u8,
+    ) -> Result<(), InvalidFeeProtocol> {
+        for fee_protocol in [fee_protocol0, fee_protocol1] {
+            if fee_protocol != 0 && !(4..=10).contains(&fee_protocol) {
+                return Err(InvalidFeeProtocol(fee_protocol));
+            }
+        }
+        *self.fee_protocol.write().unwrap() = (fee_protocol0, fee_protocol1);
+        Ok(())
+    }
+
+    // [collect_protocol] withdraws up to the requested amounts of the accrued protocol fees from the pool's balances and returns what was withdrawn.
+    pub fn collect_protocol(&self, amount0_requested: f64, amount1_requested: f64) -> (f64, f64) {
+        let mut fees = self.protocol_fees.write().unwrap();
+        let amount0 = amount0_requested.min(fees.0);
+        let amount1 = amount1_requested.min(fees.1);
+        fees.0 -= amount0;
+        fees.1 -= amount1;
+        *self.balance_0.write().unwrap() -= amount0;
+        *self.balance_1.write().unwrap() -= amount1;
+        (amount0, amount1)
+    }
+
+    // [set_block_timestamp] moves the pool's clock, which only ever runs forward.
+    pub fn set_block_timestamp(&self, timestamp: u64) {
+        let mut now = self.block_timestamp.write().unwrap();
+        *now = timestamp.max(*now);
+    }
+
+    // [write_observation] records the tick and liquidity that held until now. As in Uniswap it runs before a swap moves the tick or a mint changes in-range liquidity.
+    pub(crate) fn write_observation(&self, tick: i32, liquidity: f64) {
+        let now = *self.block_timestamp.read().unwrap();
+        self.oracle.write().unwrap().write(now, tick, liquidity);
+    }
+
+    // [observe] returns (tick_cumulative, seconds_per_liquidity_cumulative) for each of seconds_agos before the pool's clock.
+    pub fn observe(&self, seconds_agos: &[u64]) -> Result<Vec<(i64, f64)>, OracleError> {
+        self.oracle.read().unwrap().observe(
+            *self.block_timestamp.read().unwrap(),
+            seconds_agos,
+            *self.tick.read().unwrap(),
+            *self.liquidity.read().unwrap(),
+        )
+    }
+
+    pub fn increase_observation_cardinality_next(&self, next: usize) {
+        self.oracle.write().unwrap().grow(next);
+    }
+
+    // [flash] lends amount0 and amount1 out of the pool's balances and charges fee on each, like UniswapV3Pool.flash. Negative and NaN amounts are refused. callback receives the amounts and returns what it pays back. Anything short of the amount plus its fee fails the loan and leaves the balances alone. The fees paid are returned; they stay in the pool's balances, since this model has no fee growth to credit them to.
+    pub fn flash<F>(
+        &self,
+        amount0: f64,
+        amount1: f64,
+        fee: f64,
+        callback: F,
+    ) -> Result<(f64, f64), FlashError>
+    where
+        F: FnOnce(f64, f64) -> (f64, f64),
+    {
+        for (token, amount) in [(self.token_0, amount0), (self.token_1, amount1)] {
+            if amount.is_nan() || amount < 0. {
+                return Err(FlashError::InvalidAmount { token, amount });
+            }
+        }
+        let balance_0 = *self.balance_0.read().unwrap();
+        let balance_1 = *self.balance_1.read().unwrap();
+        if amount0 > balance_0 {
+            return Err(FlashError::InsufficientLiquidity {
+                token: self.token_0,
+                requested: amount0,
+                available: balance_0,
+            });
+        }
+        if amount1 > balance_1 {
+            return Err(FlashError::InsufficientLiquidity {
+                token: self.token_1,
+                requested: amount1,
+                available: balance_1,
+            });
+        }
+
+        let (paid0, paid1) = callback(amount0, amount1);
+
+        let owed0 = amount0 * (1. + fee);
+        let owed1 = amount1 * (1. + fee);
+        if paid0 < owed0 {
+            return Err(FlashError::Underpaid {
+                token: self.token_0,
+                owed: owed0,
+                repaid: paid0,
+            });
+        }
+        if paid1 < owed1 {
+            return Err(FlashError::Underpaid {
+                token: self.token_1,
+                owed: owed1,
+                repaid: paid1,
+            });
+        }
+        *self.balance_0.write().unwrap() += paid0 - amount0;
+        *self.balance_1.write().unwrap() += paid1 - amount1;
+        Ok((paid0 - amount0, paid1 - amount1))
+    }
+
+    pub fn update(&mut self, tick: i32, liquidity_delta: f64) -> bool {
+        let default_tick = Tick {
+            liquidity: RwLock::new(0.),
+            liquidity_net: RwLock::new(0.),
+            initialized: RwLock::new(false),
+        };
+        let tick_map = &mut self.tick_mapping.write().unwrap();
+
+        let info = tick_map.entry(tick).or_insert(default_tick);
+
+        let liquidity_before = *info.liquidity.read().unwrap();
+
+        let liquidity_after = liquidity_before + liquidity_delta;
+
+        if liquidity_before == 0. {
+            *info.initialized.write().unwrap() = true;
+            self.liquidity_mapping
+                .write()
+                .unwrap()
+                .insert(tick, liquidity_after);
+        }
+
+        *info.liquidity.write().unwrap() = liquidity_after;
+
+        (liquidity_after == 0.) != (liquidity_before == 0.)
+    }
+
+    fn _update_position(
+        &mut self,
+        owner: &Trader,
+        lower_tick: i32,
+        upper_tick: i32,
+        liquidity_delta: f64,
+    ) {
+        let flipped_lower = self.update(lower_tick, liquidity_delta);
+        let flipped_upper = self.update(upper_tick, liquidity_delta);
+        {
+            let tick_map = self.tick_mapping.read().unwrap();
+            *tick_map[&lower_tick].liquidity_net.write().unwrap() += liquidity_delta;
+            *tick_map[&upper_tick].liquidity_net.write().unwrap() -= liquidity_delta;
+        }
+
+        if flipped_lower {
+            self.liquidity_mapping
+                .write()
+                .unwrap()
+                .insert(lower_tick, 1.);
+        }
+        if flipped_upper {
+            self.liquidity_mapping
+                .write()
+                .unwrap()
+                .insert(upper_tick, 1.);
+        }
+
+        let default_position = Position {
+            liquidity: RwLock::new(0.),
+        };
+
+        let position_map = &mut self.position_mapping.write().unwrap();
+
+        let position = position_map.entry(owner.id).or_insert(default_position);
+
+        *position.liquidity.write().unwrap() += liquidity_delta;
+
+        if liquidity_delta < 0. {
+            if flipped_lower {
+                self.liquidity_mapping.write().unwrap().remove(&lower_tick);
+            }
+            if flipped_upper {
+                self.liquidity_mapping.write().unwrap().remove(&upper_tick);
+            }
+        }
+    }
+
+    fn _modify_position(
+        &mut self,
+        owner: &Trader,
+        lower_tick: i32,
+        upper_tick: i32,
+        liquidity_delta: f64,
+    ) -> (f64, f64) {
+        let mut amount0: f64 = 0.;
+        let mut amount1: f64 = 0.;
+        let sqrt_price_x96 = *self.sqrt_price_x96.read().unwrap();
+        let tick = *self.tick.read().unwrap();
+        self._update_position(owner, lower_tick, upper_tick, liquidity_delta);
+        if liquidity_delta != 0. {
+            if tick < lower_tick {
+                amount0 = calc_amount0(
+                    liquidity_delta,
+                    tick_to_price(lower_tick),
+                    tick_to_price(upper_tick),
+                );
+            } else if tick < upper_tick {
+                amount0 = calc_amount0(liquidity_delta, sqrt_price_x96, tick_to_price(upper_tick));
+
+                amount1 = calc_amount1(liquidity_delta, tick_to_price(lower_tick), sqrt_price_x96);
+                self.write_observation(tick, *self.liquidity.read().unwrap());
+                *self.liquidity.write().unwrap() += liquidity_delta;
+            } else {
+                amount1 = calc_amount1(
+                    liquidity_delta,
+                    tick_to_price(lower_tick),
+                    tick_to_price(upper_tick),
+                );
+            }
+        }
+
+        (amount0, amount1)
+    }
+
+    // [mint] refuses a position on an uninitialized pool, or with a tick that is not a multiple of the tick spacing. An empty or out of range position does nothing.
+    pub fn mint(
+        &mut self,
+        owner: &Trader,
+        lower_tick: i32,
+        upper_tick: i32,
+        liquidity_delta: f64,
+    ) -> Result<(), PoolError> {
+        if !self.is_initialized() {
+            return Err(PoolError::Uninitialized);
+        }
+        for tick in [lower_tick, upper_tick] {
+            if tick % self.tick_spacing != 0 {
+                return Err(PoolError::TickNotSpaced {
+                    tick,
+                    tick_spacing: self.tick_spacing,
+                });
+            }
+        }
+        if !(lower_tick >= upper_tick || lower_tick < self.min_tick || upper_tick > self.max_tick)
+            && liquidity_delta != 0.
+        {
+            let (amount0, amount1) =
+                self._modify_position(owner, lower_tick, upper_tick, liquidity_delta);
+            if amount0 > 0. {
+                *self.balance_0.write().unwrap() += amount0
+            }
+            if amount1 > 0. {
+                *self.balance_1.write().unwrap() += amount1
+            }
+
+            if self.token_0 == Token::Eth {
+                *owner.amt_eth.write().unwrap() -= amount0;
+                *owner.amt_dai.write().unwrap() -= amount1;
+            } else {
+                *owner.amt_eth.write().unwrap() -= amount1;
+                *owner.amt_dai.write().unwrap() -= amount0;
+            }
+        }
+        Ok(())
+    }
+
+    // [swap_to_price] walks initialized ticks from the current sqrt price to target_sqrt_price_x96 with [compute_swap_step] and returns what that swap takes and pays. The pool is not modified. It meets and crosses ticks in the same order as [v3_swap], so swapping amount_in lands on the target, and the fee comes off the output as in [v3_swap]. The target must be a finite price inside MIN_SQRT_RATIO..MAX_SQRT_RATIO, and the pool must be initialized.
+    pub fn swap_to_price(
+        &self,
+        target_sqrt_price_x96: f64,
+        fee: f64,
+    ) -> Result<TargetSwap, PoolError> {
+        if !self.is_initialized() {
+            return Err(PoolError::Uninitialized);
+        }
+        if !(math::get_min_sqrt_ratio()..math::get_max_sqrt_ratio())
+            .contains(&target_sqrt_price_x96)
+        {
+            return Err(PoolError::PriceOutOfBounds(target_sqrt_price_x96));
+        }
+        let mut sqrt_price_x96 = *self.sqrt_price_x96.read().unwrap();
+        let zero_for_one = target_sqrt_price_x96 < sqrt_price_x96;
+        let mut liquidity = *self.liquidity.read().unwrap();
+        let liquidity_mapping = self.liquidity_mapping.read().unwrap();
+        let tick_mapping = self.tick_mapping.read().unwrap();
+        let mut tick = *self.tick.read().unwrap();
+
+        let mut amount_in = 0.;
+        let mut gross_out = 0.;
+        let mut ticks_crossed = 0;
+        loop {
+            // The next tick v3_swap would meet, if the swap reaches it before the target.
+            let next_tick =
+                next_initialized_tick(&liquidity_mapping, tick, !zero_for_one).filter(|t| {
+                    if zero_for_one {
+                        tick_to_price(*t) > target_sqrt_price_x96
+                    } else {
+                        tick_to_price(*t) < target_sqrt_price_x96
+                    }
+                });
+            let step_target = next_tick.map_or(target_sqrt_price_x96, tick_to_price);
+            let (next_sqrt_price_x96, step_in, step_out) =
+                compute_swap_step(sqrt_price_x96, step_target, liquidity, f64::INFINITY);
+            amount_in += step_in;
+            gross_out += step_out;
+            sqrt_price_x96 = next_sqrt_price_x96;
+
+            let Some(next_tick) = next_tick else {
+                break;
+            };
+            let liquidity_delta = cross(&tick_mapping, next_tick);
+            if zero_for_one {
+                liquidity -= liquidity_delta;
+                tick = next_tick - 1;
+            } else {
+                liquidity += liquidity_delta;
+                tick = next_tick;
+            }
+            ticks_crossed += 1;
+        }
+
+        Ok(TargetSwap {
+            token_in: if zero_for_one {
+                self.token_0
+            } else {
+                self.token_1
+            },
+            amount_in,
+            amount_out: gross_out * (1. - fee),
+            fee_amount: gross_out * fee,
+            sqrt_price_x96,
+            ticks_crossed,
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct InvalidFeeProtocol(pub u8);
+
+impl fmt::Display for InvalidFeeProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fee protocol {} must be 0 or between 4 and 10", self.0)
+    }
+}
+
+impl std::error::Error for InvalidFeeProtocol {}
+
+#[derive(PartialEq, Debug)]
+pub enum PoolError {
+    UnsupportedFee(f64),
+    AlreadyInitialized,
+    PriceOutOfBounds(f64),
+    Uninitialized,
+    TickNotSpaced { tick: i32, tick_spacing: i32 },
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoolError::UnsupportedFee(fee) => write!(f, "no tick spacing for fee {}", fee),
+            PoolError::AlreadyInitialized => write!(f, "pool is already initialized"),
+            PoolError::PriceOutOfBounds(price) => {
+                write!(f, "sqrt price {} is outside the tick range", price)
+            }
+            PoolError::Uninitialized => write!(f, "pool has not been initialized"),
+            PoolError::TickNotSpaced { tick, tick_spacing } => write!(
+                f,
+                "tick {} is not a multiple of the tick spacing {}",
+                tick, tick_spacing
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+// [TargetSwap] is the result of [uniswap_v3_pool::swap_to_price]. fee_amount is in the output token.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct TargetSwap {
+    pub token_in: Token,
+    pub amount_in: f64,
+    pub amount_out: f64,
+    pub fee_amount: f64,
+    pub sqrt_price_x96: f64,
+    pub ticks_crossed: i32,
+}
+
+pub struct SwapState {
+    pub amount_specified_remaining: f64,
+    pub amount_calculated: f64,
+    pub sqrt_price_x96: f64,
+    pub tick: i32,
+    pub liquidity: f64,
+}
+
+pub struct StepState {
+    pub sqrt_price_start_x96: f64,
+    pub next_tick: i32,
+    pub sqrt_price_next_x96: f64,
+    pub amount_in: f64,
+    pub amount_out: f64,
+}
+
+// [next_initialized_tick] is the nearest initialized tick a swap from tick meets next: the greatest at or below tick going down, the least above it going up, as in Uniswap's TickBitmap. None when there is no initialized tick that way.
+pub fn next_initialized_tick(
+    liquidity_mapping: &HashMap<i32, f64>,
+    tick: i32,
+    is_up: bool,
+) -> Option<i32> {
+    let ticks = liquidity_mapping.keys().copied();
+    if is_up {
+        ticks.filter(|t| *t > tick).min()
+    } else {
+        ticks.filter(|t| *t <= tick).max()
+    }
+}
+
+// [cross] is the liquidity_net of next_tick, which crossing it upward adds to the active liquidity and crossing it downward removes.
+pub fn cross(tick_mapping: &HashMap<i32, Tick>, next_tick: i32) -> f64 {
+    let tick = tick_mapping.get(&next_tick).unwrap();
+    *tick.liquidity_net.read().unwrap()
+}
+
+// [v3_swap] returns the number of initialized ticks crossed. It refuses an uninitialized pool.
+pub fn v3_swap(
+    trader: &mut Trader,
+    pool: &uniswap_v3_pool,
+    token_in: Token,
+    amount_specified: f64,
+    fee: f64,
+) -> Result<i32, PoolError> {
+    if !pool.is_initialized() {
+        return Err(PoolError::Uninitialized);
+    }
+    let zero_for_one: bool = token_in == pool.token_0;
+    let mut ticks_crossed = 0;
+    let tick_start = *pool.tick.read().unwrap();
+    let liquidity_start = *pool.liquidity.read().unwrap();
+
+    let mut state = SwapState {
+        amount_specified_remaining: amount_specified,
+        amount_calculated: 0.,
+        sqrt_price_x96: *pool.sqrt_price_x96.read().unwrap(),
+        tick: *pool.tick.read().unwrap(),
+        liquidity: *pool.liquidity.read().unwrap(),
+    };
+
+    // Past the last initialized tick the price may run to the bound of its direction.
+    let bound = if zero_for_one {
+        math::get_min_sqrt_ratio()
+    } else {
+        math::get_max_sqrt_ratio()
+    };
+    while state.amount_specified_remaining > 0. {
+        let next_tick = next_initialized_tick(
+            &pool.liquidity_mapping.read().unwrap(),
+            state.tick,
+            !zero_for_one,
+        );
+        let sqrt_price_next_x96 = next_tick.map_or(bound, tick_to_price);
+
+        let (next_sqrt_price_x96, amount_in, amount_out) = compute_swap_step(
+            state.sqrt_price_x96,
+            sqrt_price_next_x96,
+            state.liquidity,
+            state.amount_specified_remaining,
+        );
+
+        state.sqrt_price_x96 = next_sqrt_price_x96;
+        state.amount_specified_remaining -= amount_in;
+        state.amount_calculated += amount_out;
+
+        match next_tick {
+            // As in Uniswap, a tick crossed downward leaves the swap one tick below it.
+            Some(next_tick) if state.sqrt_price_x96 == sqrt_price_next_x96 => {
+                let liquidity_delta = cross(&pool.tick_mapping.read().unwrap(), next_tick);
+                if zero_for_one {
+                    state.liquidity -= liquidity_delta;
+                    state.tick = next_tick - 1;
+                } else {
+                    state.liquidity += liquidity_delta;
+                    state.tick = next_tick;
+                }
+                ticks_crossed += 1;
+            }
+            _ => {
+                state.tick = sqrt_price_to_tick(state.sqrt_price_x96);
+                if amount_in == 0. {
+                    break;
+                }
+            }
+        }
+    }
+
+    if state.tick != tick_start {
+        pool.write_observation(tick_start, liquidity_start);
+    }
+    *pool.tick.write().unwrap() = state.tick;
+    *pool.sqrt_price_x96.write().unwrap() = state.sqrt_price_x96;
+    *pool.liquidity.write().unwrap() = state.liquidity;
+    let (amount0, amount1) = if zero_for_one {
+        (
+            amount_specified - state.amount_specified_remaining,
+            state.amount_calculated,
+        )
+    } else {
+        (
+            state.amount_calculated,
+            amount_specified - state.amount_specified_remaining,
+        )
+    };
+
+    // The fee comes off the output and stays in the pool. When the fee switch is on the protocol's cut of it is set aside in protocol_fees.
+    let (fee_protocol0, fee_protocol1) = *pool.fee_protocol.read().unwrap();
+    if zero_for_one {
+        *pool.balance_0.write().unwrap() += amount0;
+        *pool.balance_1.write().unwrap() -= (1. - fee) * amount1;
+        if fee_protocol1 != 0 {
+            pool.protocol_fees.write().unwrap().1 += fee * amount1 / fee_protocol1 as f64;
+        }
+    } else {
+        *pool.balance_0.write().unwrap() -= (1. - fee) * amount0;
+        *pool.balance_1.write().unwrap() += amount1;
+        if fee_protocol0 != 0 {
+            pool.protocol_fees.write().unwrap().0 += fee * amount0 / fee_protocol0 as f64;
+        }
+    }
+    if token_in == Token::Eth {
+        *trader.amt_eth.write().unwrap() -= amount0;
+        *trader.amt_dai.write().unwrap() += (1. - fee) * amount1;
+    } else {
+        *trader.amt_dai.write().unwrap() -= amount1;
+        *trader.amt_eth.write().unwrap() += (1. - fee) * amount0;
+    }
+    Ok(ticks_crossed)
+}
+
+pub struct Trader {
+    pub id: i32,
+    pub amt_eth: RwLock<f64>,
+    pub amt_dai: RwLock<f64>,
+}
+
+impl Trader {
+    pub fn balance(&self, token: Token) -> f64 {
+        match token {
+            Token::Eth => *self.amt_eth.read().unwrap(),
+            Token::Dai => *self.amt_dai.read().unwrap(),
+        }
+    }
+
+    // [deposit] adds amount of token to the trader's balance, or takes it away if amount is negative.
+    pub fn deposit(&self, token: Token, amount: f64) {
+        match token {
+            Token::Eth => *self.amt_eth.write().unwrap() += amount,
+            Token::Dai => *self.amt_dai.write().unwrap() += amount,
+        }
+    }
+}
+
+// [v3_quote] swaps against a private copy of the pool and returns the amount of the other token received. The pool itself is left untouched.
+pub fn v3_quote(pool: &uniswap_v3_pool, token_in: Token, amount_in: f64, fee: f64) -> f64 {
+    v3_quote_with_ticks(pool, token_in, amount_in, fee).0
+}
+
+// [v3_quote_with_ticks] is [v3_quote] that also returns the number of initialized ticks the swap crossed, for gas estimation. An uninitialized pool has no price, so it quotes nothing.
+pub fn v3_quote_with_ticks(
+    pool: &uniswap_v3_pool,
+    token_in: Token,
+    amount_in: f64,
+    fee: f64,
+) -> (f64, i32) {
+    let pool_copy = pool.clone();
+    let mut example_trader = Trader {
+        id: 1,
+        amt_dai: RwLock::new(0.),
+        amt_eth: RwLock::new(0.),
+    };
+    match v3_swap(&mut example_trader, &pool_copy, token_in, amount_in, fee) {
+        Ok(ticks_crossed) => (example_trader.balance(token_in.other()), ticks_crossed),
+        Err(_) => (0., 0),
+    }
+}
+
+// Swap fee charged on each leg of a two-pool V3 arbitrage.
+pub const ARB_FEE: f64 = 0.03;
+
+// [two_pool_arb] runs the arbitrage of x_in with no starting inventory. It flash borrows x_in of token_in from pool2 at pool2's fee, sells it into pool1 and the proceeds into pool2, and repays the loan and its fee out of what comes back, like [v2::flash_arb_profit]. The first swap cannot run in the lending pool, so pool1 and pool2 must differ. An arbitrage that cannot repay fails with the pool's error. The pools are copied, so neither changes.
+fn two_pool_arb(
+    x_in: f64,
+    pool1: &uniswap_v3_pool,
+    pool2: &uniswap_v3_pool,
+    token_in: Token,
+) -> Result<(f64, f64, i32), FlashError> {
+    if std::ptr::eq(pool1, pool2) {
+        return Err(FlashError::Reentrant);
+    }
+    let pool1_copy = pool1.clone();
+    let lender = pool2.clone();
+    let mut example_trader = Trader {
+        id: 1,
+        amt_dai: RwLock::new(0.),
+        amt_eth: RwLock::new(0.),
+    };
+    let borrow_0 = token_in == lender.token_0;
+    let (amount0, amount1) = if borrow_0 { (x_in, 0.) } else { (0., x_in) };
+    let mut mid = 0.;
+    let mut ticks_crossed = 0;
+
+    lender.flash(amount0, amount1, lender.fee, |_, _| {
+        example_trader.deposit(token_in, x_in);
+        // A refused swap leaves the trader holding the loan, which the repayment check then judges.
+        let ticks1 =
+            v3_swap(&mut example_trader, &pool1_copy, token_in, x_in, ARB_FEE).unwrap_or(0);
+        mid = example_trader.balance(token_in.other());
+        let ticks2 =
+            v3_swap(&mut example_trader, &lender, token_in.other(), mid, ARB_FEE).unwrap_or(0);
+        ticks_crossed = ticks1 + ticks2;
+        let owed = x_in * (1. + lender.fee);
+        let repaid = example_trader.balance(token_in).min(owed);
+        example_trader.deposit(token_in, -repaid);
+        if borrow_0 {
+            (repaid, 0.)
+        } else {
+            (0., repaid)
+        }
+    })?;
+
+    Ok((mid, example_trader.balance(token_in), ticks_crossed))
+}
+
+// [calc_two_pool_arb_profit] is what the flash arbitrage of x_in leaves the trader in token_in after repaying the loan.
+pub fn calc_two_pool_arb_profit(
+    x_in: f64,
+    pool1: &uniswap_v3_pool,
+    pool2: &uniswap_v3_pool,
+    token_in: Token,
+) -> Result<f64, FlashError> {
+    Ok(two_pool_arb(x_in, pool1, pool2, token_in)?.1)
+}
+
+fn two_pool_gas_cost(
+    ticks_crossed: i32,
+    token_in: Token,
+    gas: &GasModel,
+    reference: &v2::Pool,
+) -> f64 {
+    let gas_units = gas.tx_gas(gas.v3_hop_gas(ticks_crossed) + gas.v3_hop_gas(0));
+    gas.cost_in_token(gas_units, token_in, reference)
+}
+
+// [calc_two_pool_arb_gas] returns the gas cost of the two-hop arbitrage in units of token_in. It is charged whether or not the arbitrage can repay, since a reverted transaction still pays for gas.
+pub fn calc_two_pool_arb_gas(
+    x_in: f64,
+    pool1: &uniswap_v3_pool,
+    pool2: &uniswap_v3_pool,
+    token_in: Token,
+    gas: &GasModel,
+    reference: &v2::Pool,
+) -> f64 {
+    let (mid, ticks1) = v3_quote_with_ticks(pool1, token_in, x_in, ARB_FEE);
+    let (_, ticks2) = v3_quote_with_ticks(pool2, token_in.other(), mid, ARB_FEE);
+    two_pool_gas_cost(ticks1 + ticks2, token_in, gas, reference)
+}
+
+// [calc_two_pool_arb_net_profit] is [calc_two_pool_arb_profit] less gas, both in units of token_in.
+pub fn calc_two_pool_arb_net_profit(
+    x_in: f64,
+    pool1: &uniswap_v3_pool,
+    pool2: &uniswap_v3_pool,
+    token_in: Token,
+    gas: &GasModel,
+    reference: &v2::Pool,
+) -> Result<f64, FlashError> {
+    let (_, profit, ticks_crossed) = two_pool_arb(x_in, pool1, pool2, token_in)?;
+    Ok(profit - two_pool_gas_cost(ticks_crossed, token_in, gas, reference))
+}
+
+// [two_pool_opportunity] quotes both legs of the arbitrage for x_in and packages them with gas and per-leg minimum outputs. It fails if the flash loan cannot be repaid.
+pub fn two_pool_opportunity(
+    x_in: f64,
+    pool1: &uniswap_v3_pool,
+    pool2: &uniswap_v3_pool,
+    token_in: Token,
+    gas: &GasModel,
+    reference: &v2::Pool,
+    config: &OpportunityConfig,
+) -> Result<Opportunity, FlashError> {
+    let (mid, profit, ticks_crossed) = two_pool_arb(x_in, pool1, pool2, token_in)?;
+    let gas_cost = two_pool_gas_cost(ticks_crossed, token_in, gas, reference);
+    Ok(Opportunity {
+        token_in,
+        amount_in: x_in,
+        gross_profit: profit,
+        gas_cost,
+        net_profit: profit - gas_cost,
+        legs: vec![
+            config.leg(Venue::V3, token_in, x_in, mid),
+            config.leg(Venue::V3, token_in.other(), mid, x_in + profit),
+        ],
+    })
+}
+
+pub fn find_optimal_arb(
+    pool1: &uniswap_v3_pool,
+    pool2: &uniswap_v3_pool,
+    token_in: Token,
+    max_amt_in: f64,
+    gas: &GasModel,
+    reference: &v2::Pool,
+) -> f64 {
+    find_optimal_arb_until(
+        pool1,
+        pool2,
+        token_in,
+        max_amt_in,
+        gas,
+        reference,
+        &CancellationToken::new(),
+    )
+    .amount_in
+}
+
+// [find_optimal_arb_until] is [find_optimal_arb] that checks cancel before every quote and stops early, marking the outcome stale, once it fires. A max_amt_in the steps cannot get past, such as infinity, NaN or a value too large for a step to change, is not searched and gives zero.
+pub fn find_optimal_arb_until(
+    pool1: &uniswap_v3_pool,
+    pool2: &uniswap_v3_pool,
+    token_in: Token,
+    max_amt_in: f64,
+    gas: &GasModel,
+    reference: &v2::Pool,
+    cancel: &CancellationToken,
+) -> SearchOutcome {
+    let step = 100.;
+    if max_amt_in.is_nan() || max_amt_in + step == max_amt_in {
+        return SearchOutcome {
+            amount_in: 0.,
+            stale: cancel.is_cancelled(),
+        };
+    }
+    let mut amt = 1.;
+    let mut max_out = f64::MIN;
+    let mut opt_amt = 0.;
+    while amt <= max_amt_in {
+        if cancel.is_cancelled() {
+            return SearchOutcome {
+                amount_in: opt_amt,
+                stale: true,
+            };
+        }
+        let amt_out = calc_two_pool_arb_net_profit(amt, pool1, pool2, token_in, gas, reference)
+            .unwrap_or(f64::MIN);
+        if amt_out > max_out {
+            max_out = amt_out;
+            opt_amt = amt;
+        }
+        amt += step;
+    }
+    SearchOutcome {
+        amount_in: opt_amt,
+        stale: cancel.is_cancelled(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::state::SharedPool;
+
+    fn set_up_pool(
+        mint: bool,
+        lower_tick: i32,
+        upper_tick: i32,
+        liquidity: f64,
+    ) -> (Trader, uniswap_v3_pool) {
+        let trader = Trader {
+            id: 2,
+            amt_eth: RwLock::new(10000000000.),
+            amt_dai: RwLock::new(10000000000.),
+        };
+        let mut pool =
+            uniswap_v3_pool::new(Token::Eth, Token::Dai, 5602277097478614198912276234240.);
+        if mint {
+            pool.mint(&trader, lower_tick, upper_tick, liquidity)
+                .unwrap();
+        }
+
+        (trader, pool)
+    }
+
+    #[test]
+    fn price_to_sqrt_price() {
+        assert_eq!(price_to_sqrtp(5000.), 5.602277097478614e30);
+    }
+
+    #[test]
+    fn v3_test_mint() {
+        let trader = Trader {
+            id: 2,
+            amt_eth: RwLock::new(2000.),
+            amt_dai: RwLock::new(10000.),
+        };
+        let mut pool =
+            uniswap_v3_pool::new(Token::Eth, Token::Dai, 5602277097478614198912276234240.);
+
+        pool.mint(&trader, 8422, 8613, 1517882343751509868544.)
+            .unwrap();
+
+        assert_eq!(
+            *pool.sqrt_price_x96.read().unwrap(),
+            5602277097478614198912276234240.0
+        );
+    }
+    #[test]
+    fn v3_test_remove() {
+        let trader = Trader {
+            id: 2,
+            amt_eth: RwLock::new(2000.),
+            amt_dai: RwLock::new(10000.),
+        };
+        let mut pool =
+            uniswap_v3_pool::new(Token::Eth, Token::Dai, 5602277097478614198912276234240.);
+
+        pool.mint(&trader, 8422, 8613, 1517882343751509868544.)
+            .unwrap();
+
+        let liq = *pool.liquidity.read().unwrap();
+
+        assert_eq!(liq, 1517882343751509868544.);
+
+        pool.mint(&trader, 8422, 8613, -1517882343751509868544.)
+            .unwrap();
+
+        assert_eq!(
+            *pool.sqrt_price_x96.read().unwrap(),
+            5602277097478614198912276234240.0
+        );
+        let new_liquidity = *pool.liquidity.read().unwrap();
+        assert_eq!(new_liquidity, 0.)
+    }
+
+    #[test]
+    fn test_swap_eth() {
+        let (mut trader, pool) = set_up_pool(true, -86000, 86000, 100000000000.);
+        let original = *trader.amt_eth.read().unwrap();
+        let og_dai = *trader.amt_dai.read().unwrap();
+
+        v3_swap(&mut trader, &pool, Token::Eth, 1000000., 0.03).unwrap();
+
+        let post = *trader.amt_eth.read().unwrap();
+        let post_dai = *trader.amt_dai.read().unwrap();
+
+        assert!(original > post);
+        assert!(post_dai > og_dai);
+    }
+
+    #[test]
+    fn test_swap_dai() {
+        let (mut trader, pool) = set_up_pool(true, -86000, 86000, 10000000000000.);
+        let original = *trader.amt_eth.read().unwrap();
+        let og_dai = *trader.amt_dai.read().unwrap();
+
+        v3_swap(&mut trader, &pool, Token::Dai, 100., 0.03).unwrap();
+
+        let post = *trader.amt_eth.read().unwrap();
+        let post_dai = *trader.amt_dai.read().unwrap();
+
+        assert!(original < post);
+        assert!(post_dai < og_dai);
+    }
+
+    #[test]
+    fn swap_to_price_within_range() {
+        let (_, pool) = set_up_pool(true, -86000, 86000, 1000000000000000000.);
+        let current = *pool.sqrt_price_x96.read().unwrap();
+        let target = current * 1.01;
+
+        let swap = pool.swap_to_price(target, 0.03).unwrap();
+
+        assert_eq!(swap.token_in, Token::Dai);
+        assert_eq!(swap.ticks_crossed, 0);
+        assert_eq!(swap.sqrt_price_x96, target);
+        assert_eq!(
+            swap.amount_in,
+            calc_amount1(1000000000000000000., current, target)
+        );
+        let gross_out = calc_amount0(1000000000000000000., current, target);
+        assert!((swap.amount_out - gross_out * 0.97).abs() <= gross_out * 1e-12);
+        assert!((swap.fee_amount - gross_out * 0.03).abs() <= gross_out * 1e-12);
+        assert_eq!(*pool.sqrt_price_x96.read().unwrap(), current);
+
+        let down = pool.swap_to_price(current / 1.01, 0.03).unwrap();
+        assert_eq!(down.token_in, Token::Eth);
+        assert!(down.amount_in > 0. && down.amount_out > 0.);
+    }
+
+    #[test]
+    fn swap_to_price_rejects_unreachable_targets() {
+        let (_, pool) = set_up_pool(true, -86000, 86000, 1000000000000000000.);
+
+        for target in [f64::NAN, f64::INFINITY, 0., -1., math::get_max_sqrt_ratio()] {
+            assert!(matches!(
+                pool.swap_to_price(target, 0.03),
+                Err(PoolError::PriceOutOfBounds(_))
+            ));
+        }
+        assert_eq!(
+            uniswap_v3_pool::create(Token::Eth, Token::Dai, 0.003)
+                .unwrap()
+                .swap_to_price(price_to_sqrtp(5000.), 0.03),
+            Err(PoolError::Uninitialized)
+        );
+    }
+
+    #[test]
+    fn crossing_a_shared_tick_moves_liquidity_by_net() {
+        let (trader, _) = set_up_pool(false, 0, 0, 0.);
+        let mut pool = uniswap_v3_pool::create(Token::Eth, Token::Dai, 0.003).unwrap();
+        pool.initialize(price_to_sqrtp(5000.)).unwrap();
+        pool.mint(&trader, 8460, 8580, 1000000000000000000.)
+            .unwrap();
+        pool.mint(&trader, 8580, 8700, 3000000000000000000.)
+            .unwrap();
+        let current = *pool.sqrt_price_x96.read().unwrap();
+        let boundary = tick_to_price(8580);
+        let target = tick_to_price(8640);
+
+        let swap = pool.swap_to_price(target, 0.).unwrap();
+
+        assert_eq!(
+            cross(&pool.tick_mapping.read().unwrap(), 8580),
+            2000000000000000000.
+        );
+        assert_eq!(swap.ticks_crossed, 1);
+        let expected = calc_amount1(1000000000000000000., current, boundary)
+            + calc_amount1(3000000000000000000., boundary, target);
+        assert!((swap.amount_in - expected).abs() <= expected * 1e-12);
+    }
+
+    #[test]
+    fn swap_to_price_crosses_initialized_tick() {
+        let (trader, mut pool) = set_up_pool(true, -86000, 86000, 1000000000000000000.);
+        pool.mint(&trader, 8600, 8700, 1000000000000000000.)
+            .unwrap();
+        let current = *pool.sqrt_price_x96.read().unwrap();
+        let boundary = tick_to_price(8600);
+        let target = tick_to_price(8650);
+
+        let swap = pool.swap_to_price(target, 0.).unwrap();
+
+        assert_eq!(swap.ticks_crossed, 1);
+        let expected = calc_amount1(1000000000000000000., current, boundary)
+            + calc_amount1(2000000000000000000., boundary, target);
+        assert!((swap.amount_in - expected).abs() <= expected * 1e-12);
+    }
+
+    #[test]
+    fn swapping_solved_amount_lands_on_target() {
+        let (trader, mut pool) = set_up_pool(true, -86000, 86000, 1000000000000000000.);
+        pool.mint(&trader, 8400, 8460, 2000000000000000000.)
+            .unwrap();
+        pool.mint(&trader, 8600, 8700, 1000000000000000000.)
+            .unwrap();
+
+        for target in [tick_to_price(8650), tick_to_price(8430)] {
+            let swap = pool.swap_to_price(target, 0.003).unwrap();
+            let pool_copy = pool.clone();
+            let (mut swapper, _) = set_up_pool(false, 0, 0, 0.);
+            let before = swapper.balance(swap.token_in.other());
+
+            let ticks_crossed = v3_swap(
+                &mut swapper,
+                &pool_copy,
+                swap.token_in,
+                swap.amount_in,
+                0.003,
+            )
+            .unwrap();
+
+            let landed = *pool_copy.sqrt_price_x96.read().unwrap();
+            assert!((landed - target).abs() <= target * 1e-9);
+            assert_eq!(ticks_crossed, swap.ticks_crossed);
+            assert!(
+                (swapper.balance(swap.token_in.other()) - before - swap.amount_out).abs()
+                    <= swap.amount_out * 1e-9
+            );
+        }
+    }
+
+    #[test]
+    fn mint_in_range_writes_observation() {
+        let (trader, mut pool) = set_up_pool(false, 0, 0, 0.);
+        pool.set_block_timestamp(10);
+        pool.mint(&trader, -86000, 86000, 4.).unwrap();
+        pool.set_block_timestamp(30);
+
+        let observed = pool.observe(&[0, 20]).unwrap();
+
+        assert_eq!(observed[0], (8521 * 30, 10. + 20. / 4.));
+        assert_eq!(observed[1], (8521 * 10, 10.));
+        assert!(matches!(
+            pool.observe(&[25]),
+            Err(OracleError::TooOld {
+                target: 5,
+                oldest: 10
+            })
+        ));
+    }
+
+    #[test]
+    fn flash_charges_fee_on_each_amount() {
+        let (_, pool) = set_up_pool(true, -86000, 86000, 100000000000.);
+        let balance_0 = *pool.balance_0.read().unwrap();
+        let balance_1 = *pool.balance_1.read().unwrap();
+
+        let short = pool.flash(1., 0., 0.003, |amount0, _| (amount0, 0.));
+        assert_eq!(
+            short,
+            Err(FlashError::Underpaid {
+                token: Token::Eth,
+                owed: 1.003,
+                repaid: 1.
+            })
+        );
+        assert!(matches!(
+            pool.flash(0., balance_1 * 2., 0.003, |_, _| (0., 0.)),
+            Err(FlashError::InsufficientLiquidity { .. })
+        ));
+        assert!(matches!(
+            pool.flash(-1., 0., 0.003, |_, _| (0., 0.)),
+            Err(FlashError::InvalidAmount { .. })
+        ));
+        assert!(matches!(
+            pool.flash(0., f64::NAN, 0.003, |_, _| (0., 0.)),
+            Err(FlashError::InvalidAmount { .. })
+        ));
+
+        let fees = pool
+            .flash(1., 10., 0.003, |amount0, amount1| {
+                (amount0 * 1.003, amount1 * 1.003)
+            })
+            .unwrap();
+        assert_eq!(fees, (1. * 1.003 - 1., 10. * 1.003 - 10.));
+        assert_eq!(*pool.balance_0.read().unwrap(), balance_0 + fees.0);
+        assert_eq!(*pool.balance_1.read().unwrap(), balance_1 + fees.1);
+    }
+
+    #[test]
+    fn fee_switch_sets_aside_protocol_share() {
+        let (_, pool) = set_up_pool(true, -86000, 86000, 100000000000.);
+        assert_eq!(pool.set_fee_protocol(3, 0), Err(InvalidFeeProtocol(3)));
+        pool.set_fee_protocol(0, 4).unwrap();
+        let balance_1 = *pool.balance_1.read().unwrap();
+        let mut trader = Trader {
+            id: 3,
+            amt_eth: RwLock::new(0.),
+            amt_dai: RwLock::new(0.),
+        };
+
+        v3_swap(&mut trader, &pool, Token::Eth, 1000000., 0.03).unwrap();
+
+        let received = trader.balance(Token::Dai);
+        let fee_amount = received / 0.97 * 0.03;
+        let (fees_0, fees_1) = *pool.protocol_fees.read().unwrap();
+        assert_eq!(fees_0, 0.);
+        assert!((fees_1 - fee_amount / 4.).abs() <= fee_amount * 1e-12);
+        assert!(
+            (balance_1 - *pool.balance_1.read().unwrap() - received).abs() <= balance_1 * 1e-12
+        );
+
+        let collected = pool.collect_protocol(0., f64::INFINITY);
+        assert_eq!(collected, (0., fees_1));
+        assert_eq!(*pool.protocol_fees.read().unwrap(), (0., 0.));
+    }
+
+    #[test]
+    fn initialize_derives_tick_from_price() {
+        let pool = uniswap_v3_pool::create(Token::Eth, Token::Dai, 0.003).unwrap();
+        assert_eq!(pool.tick_spacing, 60);
+        assert!(!pool.is_initialized());
+
+        pool.initialize(price_to_sqrtp(5000.)).unwrap();
+
+        let tick = *pool.tick.read().unwrap();
+        assert_eq!(tick, 8521);
+        assert!(tick_to_price(tick) <= price_to_sqrtp(5000.));
+        assert!(tick_to_price(tick + 1) > price_to_sqrtp(5000.));
+        assert_eq!(sqrt_price_to_tick(tick_to_price(-300)), -300);
+        assert_eq!(
+            pool.initialize(price_to_sqrtp(1.)),
+            Err(PoolError::AlreadyInitialized)
+        );
+        assert!(matches!(
+            uniswap_v3_pool::create(Token::Eth, Token::Dai, 0.03),
+            Err(PoolError::UnsupportedFee(_))
+        ));
+        let other = uniswap_v3_pool::create(Token::Eth, Token::Dai, 0.0005).unwrap();
+        assert_eq!(other.initialize(1.), Err(PoolError::PriceOutOfBounds(1.)));
+    }
+
+    #[test]
+    fn uninitialized_pool_refuses_swap_and_mint() {
+        let (mut trader, _) = set_up_pool(false, 0, 0, 0.);
+        let mut pool = uniswap_v3_pool::create(Token::Eth, Token::Dai, 0.003).unwrap();
+
+        assert_eq!(
+            pool.mint(&trader, -600, 600, 1000.),
+            Err(PoolError::Uninitialized)
+        );
+        assert_eq!(
+            v3_swap(&mut trader, &pool, Token::Eth, 1., pool.fee),
+            Err(PoolError::Uninitialized)
+        );
+        assert_eq!(*pool.liquidity.read().unwrap(), 0.);
+
+        pool.initialize(tick_to_price(0)).unwrap();
+        assert_eq!(
+            pool.mint(&trader, -610, 600, 1000.),
+            Err(PoolError::TickNotSpaced {
+                tick: -610,
+                tick_spacing: 60
+            })
+        );
+        pool.mint(&trader, -600, 600, 1000.).unwrap();
+        assert_eq!(*pool.liquidity.read().unwrap(), 1000.);
+        assert!(v3_swap(&mut trader, &pool, Token::Eth, 1., pool.fee).is_ok());
+    }
+
+    #[test]
+    fn quote_leaves_pool_untouched() {
+        let (_, pool) = set_up_pool(true, -86000, 86000, 100000000000.);
+        let before = *pool.sqrt_price_x96.read().unwrap();
+
+        let out = v3_quote(&pool, Token::Eth, 1000000., 0.03);
+
+        assert!(out > 0.);
+        assert_eq!(*pool.sqrt_price_x96.read().unwrap(), before);
+    }
+
+    fn set_up_pool_at(price: f64, liquidity: f64) -> uniswap_v3_pool {
+        let (trader, _) = set_up_pool(false, 0, 0, 0.);
+        let mut pool = uniswap_v3_pool::create(Token::Eth, Token::Dai, 0.003).unwrap();
+        pool.initialize(price_to_sqrtp(price)).unwrap();
+        pool.mint(&trader, -86040, 86040, liquidity).unwrap();
+        pool
+    }
+
+    #[test]
+    fn two_pool_arb_repays_flash_loan() {
+        let pool1 = set_up_pool_at(4000., 100000000000000.);
+        let pool2 = set_up_pool_at(5000., 100000000000000.);
+
+        let (mid, profit, _) = two_pool_arb(1000000., &pool1, &pool2, Token::Dai).unwrap();
+
+        assert_eq!(mid, v3_quote(&pool1, Token::Dai, 1000000., ARB_FEE));
+        let back = v3_quote(&pool2, Token::Eth, mid, ARB_FEE);
+        let owed = 1000000. * (1. + pool2.fee);
+        assert!(profit > 0.);
+        assert!((profit - (back - owed)).abs() <= owed * 1e-6);
+        assert!(matches!(
+            two_pool_arb(1000000., &pool2, &pool1, Token::Dai),
+            Err(FlashError::Underpaid { .. })
+        ));
+        assert_eq!(
+            two_pool_arb(1., &pool1, &pool1, Token::Eth),
+            Err(FlashError::Reentrant)
+        );
+    }
+
+    #[test]
+    fn opportunity_reports_gas_and_legs() {
+        let pool1 = set_up_pool_at(4000., 100000000000000.);
+        let pool2 = set_up_pool_at(5000., 100000000000000.);
+        let reference = v2::Pool::new(Token::Eth, Token::Dai, 4., 20000.);
+        let config = OpportunityConfig::default();
+
+        let opportunity = two_pool_opportunity(
+            1000000.,
+            &pool1,
+            &pool2,
+            Token::Dai,
+            &GasModel::default(),
+            &reference,
+            &config,
+        )
+        .unwrap();
+
+        assert!(opportunity.gas_cost > 0.);
+        assert_eq!(
+            opportunity.net_profit,
+            opportunity.gross_profit - opportunity.gas_cost
+        );
+        assert_eq!(opportunity.legs.len(), 2);
+        assert_eq!(
+            opportunity.legs[1].amount_in,
+            opportunity.legs[0].expected_out
+        );
+        assert!(opportunity.legs[0].min_out < opportunity.legs[0].expected_out);
+    }
+
+    #[test]
+    fn search_stops_when_pool_advances() {
+        let (trader, pool) = set_up_pool(true, -86000, 86000, 100000000000000.);
+        let shared1 = SharedPool::new(pool.clone());
+        let shared2 = SharedPool::new(pool);
+        let reference = v2::Pool::new(Token::Eth, Token::Dai, 4., 20000.);
+
+        let mut cancel = CancellationToken::new();
+        let state1 = cancel.read(&shared1);
+        let state2 = cancel.read(&shared2);
+        shared2.update(|p| p.mint(&trader, -86000, 86000, 20000.).unwrap());
+
+        let outcome = find_optimal_arb_until(
+            &state1.pool,
+            &state2.pool,
+            Token::Eth,
+            1000000.,
+            &GasModel::default(),
+            &reference,
+            &cancel,
+        );
+
+        assert!(outcome.stale);
+        assert_eq!(outcome.amount_in, 0.);
+    }
+
+    #[test]
+    fn search_skips_max_it_cannot_step_past() {
+        let pool1 = set_up_pool_at(4000., 100000000000000.);
+        let pool2 = set_up_pool_at(5000., 100000000000000.);
+        let reference = v2::Pool::new(Token::Eth, Token::Dai, 4., 20000.);
+
+        for max_amt_in in [f64::INFINITY, f64::NAN, 1e300] {
+            let outcome = find_optimal_arb_until(
+                &pool1,
+                &pool2,
+                Token::Dai,
+                max_amt_in,
+                &GasModel::default(),
+                &reference,
+                &CancellationToken::new(),
+            );
+            assert_eq!(outcome.amount_in, 0.);
+            assert!(!outcome.stale);
+        }
+    }
+}
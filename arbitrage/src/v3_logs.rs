@@ -0,0 +1,421 @@
+use crate::logs::{group_by_block, LogError, RawLog};
+use crate::v3::{sqrt_price_to_tick, uniswap_v3_pool, PoolError, Trader};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+pub const INITIALIZE_TOPIC: &str =
+    "0x98636036cb66a9c19a37435efc1e90142190214e8abeb821bdba3f2990dd4c95";
+pub const MINT_TOPIC: &str = "0x7a53080ba414158be7ec69b987b5fb7d07dee101fe85488f0853ae16239d0bde";
+pub const BURN_TOPIC: &str = "0x0c396cd989a39f4459b5fa1aed6a9a8dcdbc45908acfd67e028cd568da98982c";
+pub const SWAP_TOPIC: &str = "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67";
+pub const COLLECT_TOPIC: &str =
+    "0x70935338e69775456a85ddef226c395fb668b63fa0115f5f20610b388e6ca9c0";
+
+// Logs carry no block timestamp, so the replayed pool's clock runs at a fixed block time.
+pub const SECONDS_PER_BLOCK: u64 = 12;
+
+// Amounts are raw token units. Swap amounts are signed from the pool's point of view, so a positive amount was paid in.
+#[derive(PartialEq, Clone, Debug)]
+pub enum V3Event {
+    Initialize {
+        sqrt_price_x96: f64,
+        tick: i32,
+    },
+    Mint {
+        owner: String,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount: f64,
+        amount0: f64,
+        amount1: f64,
+    },
+    Burn {
+        owner: String,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount: f64,
+        amount0: f64,
+        amount1: f64,
+    },
+    Swap {
+        amount0: f64,
+        amount1: f64,
+        sqrt_price_x96: f64,
+        liquidity: f64,
+        tick: i32,
+    },
+    Collect {
+        owner: String,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount0: f64,
+        amount1: f64,
+    },
+}
+
+// [decode_v3_log] returns None for logs that are not one of the pool events we model.
+pub fn decode_v3_log(log: &RawLog) -> Result<Option<V3Event>, LogError> {
+    let topic0 = match log.topics.first() {
+        Some(topic) => topic.to_ascii_lowercase(),
+        None => return Ok(None),
+    };
+    let event = match topic0.as_str() {
+        INITIALIZE_TOPIC => {
+            let data = log.words(2)?;
+            V3Event::Initialize {
+                sqrt_price_x96: data[0].as_u256(),
+                tick: data[1].as_i24(),
+            }
+        }
+        MINT_TOPIC => {
+            let data = log.words(4)?;
+            V3Event::Mint {
+                owner: log.topic(1)?.as_address(),
+                tick_lower: log.topic(2)?.as_i24(),
+                tick_upper: log.topic(3)?.as_i24(),
+                amount: data[1].as_u256(),
+                amount0: data[2].as_u256(),
+                amount1: data[3].as_u256(),
+            }
+        }
+        BURN_TOPIC => {
+            let data = log.words(3)?;
+            V3Event::Burn {
+                owner: log.topic(1)?.as_address(),
+                tick_lower: log.topic(2)?.as_i24(),
+                tick_upper: log.topic(3)?.as_i24(),
+                amount: data[0].as_u256(),
+                amount0: data[1].as_u256(),
+                amount1: data[2].as_u256(),
+            }
+        }
+        SWAP_TOPIC => {
+            let data = log.words(5)?;
+            V3Event::Swap {
+                amount0: data[0].as_i256(),
+                amount1: data[1].as_i256(),
+                sqrt_price_x96: data[2].as_u256(),
+                liquidity: data[3].as_u256(),
+                tick: data[4].as_i24(),
+            }
+        }
+        COLLECT_TOPIC => {
+            let data = log.words(3)?;
+            V3Event::Collect {
+                owner: log.topic(1)?.as_address(),
+                tick_lower: log.topic(2)?.as_i24(),
+                tick_upper: log.topic(3)?.as_i24(),
+                amount0: data[1].as_u256(),
+                amount1: data[2].as_u256(),
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(event))
+}
+
+// [V3LogReplayer] applies one pool's events to a uniswap_v3_pool. Position owners are addresses in the logs and are given stable trader ids in order of first appearance.
+#[derive(Clone)]
+pub struct V3LogReplayer {
+    address: String,
+    owners: HashMap<String, i32>,
+}
+
+impl V3LogReplayer {
+    pub fn new(address: &str) -> Self {
+        V3LogReplayer {
+            address: address.to_string(),
+            owners: HashMap::new(),
+        }
+    }
+
+    pub fn owner_id(&mut self, owner: &str) -> i32 {
+        let next = self.owners.len() as i32 + 1;
+        *self.owners.entry(owner.to_ascii_lowercase()).or_insert(next)
+    }
+
+    fn owner(&mut self, owner: &str) -> Trader {
+        Trader {
+            id: self.owner_id(owner),
+            amt_eth: RwLock::new(0.),
+            amt_dai: RwLock::new(0.),
+        }
+    }
+
+    // The logged amounts are authoritative for pool balances, so mint and burn only use the model to move liquidity between ticks and positions. Logged ticks are in Uniswap's base 1.0001, so Initialize goes through the pool's own checks and a swap derives the tick from its logged price, keeping price and tick in agreement. A swap that moves the tick writes an oracle observation first, as [v3_swap] does.
+    pub fn apply(&mut self, pool: &mut uniswap_v3_pool, event: &V3Event) -> Result<(), PoolError> {
+        let balance_0 = *pool.balance_0.read().unwrap();
+        let balance_1 = *pool.balance_1.read().unwrap();
+        match event {
+            V3Event::Initialize { sqrt_price_x96, .. } => {
+                pool.initialize(*sqrt_price_x96)?;
+            }
+            V3Event::Mint {
+                owner,
+                tick_lower,
+                tick_upper,
+                amount,
+                amount0,
+                amount1,
+            } => {
+                let trader = self.owner(owner);
+                pool.mint(&trader, *tick_lower, *tick_upper, *amount)?;
+                *pool.balance_0.write().unwrap() = balance_0 + amount0;
+                *pool.balance_1.write().unwrap() = balance_1 + amount1;
+            }
+            V3Event::Burn {
+                owner,
+                tick_lower,
+                tick_upper,
+                amount,
+                ..
+            } => {
+                let trader = self.owner(owner);
+                pool.mint(&trader, *tick_lower, *tick_upper, -amount)?;
+                *pool.balance_0.write().unwrap() = balance_0;
+                *pool.balance_1.write().unwrap() = balance_1;
+            }
+            V3Event::Swap {
+                amount0,
+                amount1,
+                sqrt_price_x96,
+                liquidity,
+                ..
+            } => {
+                let tick = sqrt_price_to_tick(*sqrt_price_x96);
+                let tick_start = *pool.tick.read().unwrap();
+                if tick != tick_start {
+                    pool.write_observation(tick_start, *pool.liquidity.read().unwrap());
+                }
+                *pool.balance_0.write().unwrap() = balance_0 + amount0;
+                *pool.balance_1.write().unwrap() = balance_1 + amount1;
+                *pool.sqrt_price_x96.write().unwrap() = *sqrt_price_x96;
+                *pool.liquidity.write().unwrap() = *liquidity;
+                *pool.tick.write().unwrap() = tick;
+            }
+            V3Event::Collect {
+                amount0, amount1, ..
+            } => {
+                *pool.balance_0.write().unwrap() = balance_0 - amount0;
+                *pool.balance_1.write().unwrap() = balance_1 - amount1;
+            }
+        }
+        Ok(())
+    }
+
+    // [apply_log] returns whether the log was from this pool and decoded to an event we model. The pool's clock moves to the log's block first.
+    pub fn apply_log(&mut self, pool: &mut uniswap_v3_pool, log: &RawLog) -> Result<bool, LogError> {
+        if !log.is_from(&self.address) {
+            return Ok(false);
+        }
+        match decode_v3_log(log)? {
+            Some(event) => {
+                pool.set_block_timestamp(log.block_number * SECONDS_PER_BLOCK);
+                self.apply(pool, &event)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // [replay] applies ordered logs and calls on_block with the pool state at the end of each block. Returns the number of events applied.
+    pub fn replay<F: FnMut(u64, &uniswap_v3_pool)>(
+        &mut self,
+        pool: &mut uniswap_v3_pool,
+        logs: &[RawLog],
+        mut on_block: F,
+    ) -> Result<usize, LogError> {
+        let mut applied = 0;
+        for (block_number, block) in group_by_block(logs) {
+            for log in block {
+                if self.apply_log(pool, log)? {
+                    applied += 1;
+                }
+            }
+            on_block(block_number, pool);
+        }
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::token::Token;
+
+    const POOL: &str = "0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640";
+    const OWNER: &str = "0x000000000000000000000000c36442b4a4522e871399cd717abdd847ab11fe88";
+
+    fn word(value: i128) -> String {
+        let pad = if value < 0 { "f" } else { "0" };
+        format!("{}{:032x}", pad.repeat(32), value as u128)
+    }
+
+    fn log(block: u64, index: u64, topics: Vec<String>, data: &[i128]) -> RawLog {
+        RawLog {
+            address: POOL.to_string(),
+            topics,
+            data: format!("0x{}", data.iter().map(|v| word(*v)).collect::<String>()),
+            block_number: block,
+            log_index: index,
+            removed: false,
+        }
+    }
+
+    fn position_topics(topic: &str, lower: i128, upper: i128) -> Vec<String> {
+        vec![
+            topic.to_string(),
+            OWNER.to_string(),
+            format!("0x{}", word(lower)),
+            format!("0x{}", word(upper)),
+        ]
+    }
+
+    fn empty_pool() -> uniswap_v3_pool {
+        uniswap_v3_pool::new(Token::Eth, Token::Dai, 0.)
+    }
+
+    #[test]
+    fn decodes_swap() {
+        let swap = log(
+            1,
+            0,
+            vec![SWAP_TOPIC.to_string(), OWNER.to_string(), OWNER.to_string()],
+            &[-500, 2500000, 5602277097478614198912276234240, 1000000, 85176],
+        );
+
+        assert_eq!(
+            decode_v3_log(&swap).unwrap(),
+            Some(V3Event::Swap {
+                amount0: -500.,
+                amount1: 2500000.,
+                sqrt_price_x96: 5602277097478614198912276234240.,
+                liquidity: 1000000.,
+                tick: 85176,
+            })
+        );
+    }
+
+    #[test]
+    fn replays_pool_block_by_block() {
+        let logs = vec![
+            log(
+                10,
+                0,
+                vec![INITIALIZE_TOPIC.to_string()],
+                &[5602277097478614198912276234240, 85176],
+            ),
+            log(
+                10,
+                1,
+                position_topics(MINT_TOPIC, 8422, 8613),
+                &[0, 1000000, 1000, 5000000],
+            ),
+            log(
+                11,
+                0,
+                vec![SWAP_TOPIC.to_string(), OWNER.to_string(), OWNER.to_string()],
+                &[-100, 500000, 5658022521205802533423218163712, 1000000, 85180],
+            ),
+            log(
+                12,
+                0,
+                position_topics(BURN_TOPIC, 8422, 8613),
+                &[400000, 360, 2200000],
+            ),
+            log(
+                12,
+                1,
+                position_topics(COLLECT_TOPIC, 8422, 8613),
+                &[0, 360, 2200000],
+            ),
+        ];
+        let mut pool = empty_pool();
+        let mut replayer = V3LogReplayer::new(&POOL.to_uppercase().replace("0X", "0x"));
+        let mut blocks = vec![];
+
+        let applied = replayer
+            .replay(&mut pool, &logs, |block, pool| {
+                blocks.push((block, *pool.tick.read().unwrap()))
+            })
+            .unwrap();
+
+        assert_eq!(applied, 5);
+        assert_eq!(blocks, vec![(10, 8521), (11, 8541), (12, 8541)]);
+        assert_eq!(*pool.balance_0.read().unwrap(), 540.);
+        assert_eq!(*pool.balance_1.read().unwrap(), 3300000.);
+        assert_eq!(*pool.liquidity.read().unwrap(), 600000.);
+        let owner = replayer.owner_id("0xc36442b4a4522e871399cd717abdd847ab11fe88");
+        let positions = pool.position_mapping.read().unwrap();
+        assert_eq!(*positions[&owner].liquidity.read().unwrap(), 600000.);
+        assert_eq!(*pool.block_timestamp.read().unwrap(), 12 * SECONDS_PER_BLOCK);
+    }
+
+    #[test]
+    fn swap_writes_observation_when_tick_moves() {
+        let initialize = log(
+            10,
+            0,
+            vec![INITIALIZE_TOPIC.to_string()],
+            &[5602277097478614198912276234240, 85176],
+        );
+        let mint = log(
+            10,
+            1,
+            position_topics(MINT_TOPIC, 8422, 8613),
+            &[0, 1000000, 1000, 5000000],
+        );
+        let swap = |block, sqrt_price_x96| {
+            log(
+                block,
+                0,
+                vec![SWAP_TOPIC.to_string(), OWNER.to_string(), OWNER.to_string()],
+                &[-100, 500000, sqrt_price_x96, 1000000, 85176],
+            )
+        };
+        let mut pool = empty_pool();
+        let mut replayer = V3LogReplayer::new(POOL);
+        replayer.apply_log(&mut pool, &initialize).unwrap();
+        pool.increase_observation_cardinality_next(4);
+        replayer.apply_log(&mut pool, &mint).unwrap();
+
+        replayer.apply_log(&mut pool, &swap(11, 5658022521205802533423218163712)).unwrap();
+        replayer.apply_log(&mut pool, &swap(12, 5658022521205802533423218163712)).unwrap();
+        replayer.apply_log(&mut pool, &swap(13, 5545971375998503631548076523520)).unwrap();
+
+        let observed = pool.observe(&[0, 12, 24, 36]).unwrap();
+        assert_eq!(observed[3], (0, 0.));
+        assert_eq!(observed[2], (8521 * 12, 12. / 1000000.));
+        assert_eq!(observed[1], (8521 * 12 + 8541 * 12, 24. / 1000000.));
+        assert_eq!(observed[0], (8521 * 12 + 8541 * 24, 36. / 1000000.));
+        assert_eq!(*pool.tick.read().unwrap(), 8501);
+        assert_eq!(pool.oracle.read().unwrap().index, 2);
+    }
+
+    #[test]
+    fn skips_other_pools_and_events() {
+        let mut other = log(1, 0, vec![INITIALIZE_TOPIC.to_string()], &[1, 2]);
+        other.address = "0x0000000000000000000000000000000000000001".to_string();
+        let unknown = log(1, 1, vec![format!("0x{}", word(1))], &[]);
+        let mut pool = empty_pool();
+        let mut replayer = V3LogReplayer::new(POOL);
+
+        assert!(!replayer.apply_log(&mut pool, &other).unwrap());
+        assert!(!replayer.apply_log(&mut pool, &unknown).unwrap());
+        assert_eq!(*pool.tick.read().unwrap(), 0);
+    }
+
+    #[test]
+    fn initialize_checks_price_bounds() {
+        let initialize = log(1, 0, vec![INITIALIZE_TOPIC.to_string()], &[1, 2]);
+        let mut pool = empty_pool();
+        let mut replayer = V3LogReplayer::new(POOL);
+
+        assert!(matches!(
+            replayer.apply_log(&mut pool, &initialize),
+            Err(LogError::Pool(PoolError::PriceOutOfBounds(_)))
+        ));
+        assert!(!pool.is_initialized());
+    }
+}